@@ -0,0 +1,132 @@
+// gets an operator's attention when something goes wrong, for when nobody's
+// watching the screen: a terminal bell, and/or a shell command (to send a
+// notification, say) run with ALERT_KIND and ALERT_MESSAGE in its
+// environment. each kind of alert goes off at most once per
+// min_interval_secs, so a flapping link doesn't turn into a stream of them.
+
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{ self, Write };
+use std::process;
+use std::thread;
+use std::time::{ Duration, Instant };
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlertConfig {
+    pub bell: bool,                    // ring the terminal bell
+    pub command: Option<String>,       // run with sh -c
+    pub link_lost_secs: Option<u64>,   // alert after this long without a packet
+    pub low_battery_millivolts: Option<u16>,  // alert when the rover's battery is under this
+    pub low_memory: bool,              // alert when free memory is low or falling, see memory_watch.rs
+    pub min_interval_secs: u64,        // between alerts of the same kind
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            bell: true,
+            command: None,
+            link_lost_secs: Some(30),
+            low_battery_millivolts: None,
+            low_memory: true,
+            min_interval_secs: 300
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum AlertKind {
+    LinkLost,
+    LowBattery,
+    LowMemory,
+}
+
+impl AlertKind {
+    fn name(&self) -> &'static str {
+        match self {
+            AlertKind::LinkLost => "link_lost",
+            AlertKind::LowBattery => "low_battery",
+            AlertKind::LowMemory => "low_memory",
+        }
+    }
+}
+
+pub struct Alerter {
+    config: AlertConfig,
+    last: HashMap<AlertKind, Instant>,
+}
+
+impl Alerter {
+    pub fn new(config: &AlertConfig) -> Alerter {
+        Alerter { config: config.clone(), last: HashMap::new() }
+    }
+
+    // each check does nothing unless its trigger is configured
+    pub fn check_link(&mut self, since_last_packet: Duration) {
+        if let Some(secs) = self.config.link_lost_secs {
+            if since_last_packet.as_secs() >= secs {
+                self.alert(AlertKind::LinkLost, &format!("no packets from the rover for {} s", since_last_packet.as_secs()));
+            }
+        }
+    }
+
+    pub fn check_battery(&mut self, battery_millivolts: u16) {
+        if let Some(low) = self.config.low_battery_millivolts {
+            if battery_millivolts < low {
+                self.alert(AlertKind::LowBattery, &format!("rover battery at {} mV", battery_millivolts));
+            }
+        }
+    }
+
+    pub fn check_memory(&mut self, low: bool, free_memory: u16) {
+        if self.config.low_memory && low {
+            self.alert(AlertKind::LowMemory, &format!("rover memory low, {} bytes free", free_memory));
+        }
+    }
+
+    // returns whether it actually went off, rather than being rate limited
+    fn alert(&mut self, kind: AlertKind, message: &str) -> bool {
+        let interval = Duration::from_secs(self.config.min_interval_secs);
+        if matches!(self.last.get(&kind), Some(last) if last.elapsed() < interval) {
+            return false;
+        }
+        self.last.insert(kind, Instant::now());
+        warn!("ALERT: {}", message);
+        if self.config.bell {
+            // stderr, since stdout may be JSON going somewhere else
+            eprint!("\x07");
+            let _ = io::stderr().flush();
+        }
+        if let Some(script) = &self.config.command {
+            let mut command = process::Command::new("sh");
+            command.arg("-c").arg(script)
+                   .env("ALERT_KIND", kind.name())
+                   .env("ALERT_MESSAGE", message);
+            // waited for on a thread of its own so a slow command can't hold up the receive loop
+            thread::spawn(move || match command.status() {
+                Ok(status) if status.success() => (),
+                Ok(status) => warn!("Alert command failed: {}", status),
+                Err(e) => warn!("Couldn't run alert command: {}", e)
+            });
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alerts_are_rate_limited_per_kind() {
+        let mut alerter = Alerter::new(&AlertConfig { bell: false, min_interval_secs: 60, ..Default::default() });
+        assert!(alerter.alert(AlertKind::LinkLost, "link lost"));
+        assert!(!alerter.alert(AlertKind::LinkLost, "link lost"));
+        assert!(alerter.alert(AlertKind::LowMemory, "memory low"));
+        let mut alerter = Alerter::new(&AlertConfig { bell: false, min_interval_secs: 0, ..Default::default() });
+        assert!(alerter.alert(AlertKind::LinkLost, "link lost"));
+        assert!(alerter.alert(AlertKind::LinkLost, "link lost"));
+    }
+}
@@ -0,0 +1,130 @@
+// writes and reads files of captured radio frames, so packets recorded in the
+// field can be replayed through the station at a desk without the rover. a
+// capture is either text, one frame per line as hex (spaces between bytes are
+// fine, and lines starting with # are comments), or the raw frames
+// concatenated, each FRAME_SIZE bytes. FrameCapture writes the text kind.
+
+use crate::errors::*;
+use std::fs::{ self, File, OpenOptions };
+use std::io::Write;
+use std::path::Path;
+
+pub const FRAME_SIZE: usize = 64;
+
+pub struct FrameCapture {
+    file: File,
+}
+
+impl FrameCapture {
+    // open (or create) the capture at path, appending to whatever is already there
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<FrameCapture> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .chain_err(|| format!("opening capture file {}", path.display()))?;
+        Ok(FrameCapture { file })
+    }
+
+    // write one whole receive buffer, flushed immediately so a crash doesn't lose it
+    pub fn write(&mut self, frame: &[u8]) -> Result<()> {
+        let line: Vec<String> = frame.iter().map(|byte| format!("{:02x}", byte)).collect();
+        writeln!(self.file, "{}", line.join(" "))?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<[u8; FRAME_SIZE]>> {
+    let path = path.as_ref();
+    let contents = fs::read(path).chain_err(|| format!("reading capture file {}", path.display()))?;
+    let frames = if is_text(&contents) {
+        parse_hex(&String::from_utf8_lossy(&contents))
+    } else {
+        parse_binary(&contents)
+    };
+    frames.chain_err(|| format!("reading capture file {}", path.display()))
+}
+
+// real frames are full of control characters and bytes over 127, so anything
+// that's all printable ASCII is taken to be a text capture
+fn is_text(contents: &[u8]) -> bool {
+    contents.iter().all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace())
+}
+
+// a short line is padded out with zeros, which is what the FIFO would have
+// left after the packet anyway
+fn parse_hex(text: &str) -> Result<Vec<[u8; FRAME_SIZE]>> {
+    let mut frames = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let digits: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+        if digits.len() % 2 != 0 {
+            return Err(format!("line {}: odd number of hex digits", number + 1).into());
+        }
+        if digits.len() / 2 > FRAME_SIZE {
+            return Err(format!("line {}: frame is {} bytes, more than {}", number + 1, digits.len() / 2, FRAME_SIZE).into());
+        }
+        let mut frame = [0; FRAME_SIZE];
+        for (byte, pair) in frame.iter_mut().zip(digits.chunks(2)) {
+            let pair: String = pair.iter().collect();
+            *byte = match u8::from_str_radix(&pair, 16) {
+                Ok(byte) if pair.chars().all(|c| c.is_ascii_hexdigit()) => byte,
+                _ => return Err(format!("line {}: bad hex {:?}", number + 1, pair).into())
+            };
+        }
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+fn parse_binary(contents: &[u8]) -> Result<Vec<[u8; FRAME_SIZE]>> {
+    if contents.len() % FRAME_SIZE != 0 {
+        return Err(format!("binary capture is {} bytes, not a whole number of {} byte frames",
+                           contents.len(), FRAME_SIZE).into());
+    }
+    Ok(contents.chunks(FRAME_SIZE).map(|chunk| {
+        let mut frame = [0; FRAME_SIZE];
+        frame.copy_from_slice(chunk);
+        frame
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_lines_are_frames() {
+        let text = "# captured at the park\n0a ff ff 00 00\n\n0BFF\n";
+        assert!(is_text(text.as_bytes()));
+        let frames = parse_hex(text).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0][..6], [0x0a, 0xff, 0xff, 0x00, 0x00, 0x00]);
+        assert_eq!(frames[1][..3], [0x0b, 0xff, 0x00]);
+        assert!(parse_hex("0a zz").is_err());
+    }
+
+    #[test]
+    fn binary_is_split_into_frames() {
+        let mut contents = vec![0xaa; FRAME_SIZE];
+        contents.extend_from_slice(&[0x55; FRAME_SIZE]);
+        assert!(!is_text(&contents));
+        let frames = parse_binary(&contents).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1][0], 0x55);
+        assert!(parse_binary(&contents[1..]).is_err());
+    }
+
+    #[test]
+    fn written_frames_load_back() {
+        let path = std::env::temp_dir().join(format!("ground_control_capture_{}.txt", std::process::id()));
+        let mut frame = [0; FRAME_SIZE];
+        frame[..4].copy_from_slice(&[0x0a, 0xff, 0x01, 0x07]);
+        let mut capture = FrameCapture::new(&path).unwrap();
+        capture.write(&frame).unwrap();
+        capture.write(&frame).unwrap();
+        let frames = load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(frames.unwrap(), vec![frame, frame]);
+    }
+}
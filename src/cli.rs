@@ -0,0 +1,159 @@
+// command line options. anything given here overrides the config file (see
+// config.rs), which in turn overrides the built-in defaults.
+
+use crate::config::{ self, Config, StationConfig };
+use crate::errors::*;
+use crate::mqtt::MqttConfig;
+use crate::radio::SendMode;
+use clap::{ Parser, Subcommand };
+use std::env;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about = "Rover ground station")]
+pub struct Args {
+    #[arg(long, value_name = "PATH", default_value_os_t = config::default_config_path(),
+          help = "Config file; settings missing from it (or the whole file) fall back to defaults")]
+    pub config: PathBuf,
+
+    #[arg(long, value_name = "MHZ",
+          help = "Carrier frequency in MHz (the bonnet comes in 868 and 915 MHz variants) [default: 915]")]
+    pub frequency: Option<f64>,
+
+    #[arg(long, value_name = "LEVEL", help = "Transmit power level, 0-31; output is (-14 + level) dBm [default: 31]")]
+    pub power: Option<u8>,
+
+    #[arg(long, value_name = "BPS", help = "Radio bit rate in bits per second [default: 9600]")]
+    pub bit_rate: Option<f32>,
+
+    #[arg(long, help = "Turn off AES encryption, allowing longer packets (must match the rover)")]
+    pub no_encryption: bool,
+
+    #[arg(long, value_name = "PATH", help = "File holding the AES key and sync words [default: ~/.ground_control/key]")]
+    pub key_file: Option<PathBuf>,
+
+    #[arg(long, value_name = "ADDRESS", help = "Only listen to the rover with this RadioHead node id [default: any]")]
+    pub rover: Option<u8>,
+
+    #[arg(long, requires = "rover", help = "Send packets to the --rover only, instead of broadcasting them to every rover")]
+    pub addressed: bool,
+
+    #[arg(long, help = "Ask the rover for a command session after each telemetry packet")]
+    pub command: bool,
+
+    #[arg(long, value_name = "PATH", help = "Run this command script (one command per line, # for comments) at the next command session")]
+    pub commands: Option<PathBuf>,
+
+    #[arg(long, help = "Ask the rover for telemetry whenever it goes quiet")]
+    pub poll: bool,
+
+    #[arg(long, help = "Listen on each of the [scan] frequencies in turn until the rover is heard, then stay there")]
+    pub scan: bool,
+
+    #[arg(long, help = "Print telemetry as one line of JSON per packet")]
+    pub json: bool,
+
+    #[arg(long, conflicts_with_all = ["json", "command"],
+          help = "Show a full screen dashboard instead of printing telemetry (q to quit)")]
+    pub tui: bool,
+
+    #[arg(long, value_name = "HOST[:PORT]", help = "Publish telemetry to this MQTT broker")]
+    pub mqtt: Option<String>,
+
+    #[arg(long, value_name = "TOPIC", help = "MQTT topic to publish telemetry to [default: rover/telemetry]")]
+    pub mqtt_topic: Option<String>,
+
+    #[arg(long, value_name = "PORT", help = "Serve the latest telemetry as JSON over HTTP on this port")]
+    pub http: Option<u16>,
+
+    #[arg(long, value_name = "SECS", help = "Ping the rover this often to measure the link round trip time")]
+    pub ping: Option<u64>,
+
+    #[arg(long, value_name = "PATH", help = "Append every frame received to this file, for the replay subcommand")]
+    pub capture: Option<PathBuf>,
+
+    #[arg(long, value_name = "LAT,LON", allow_hyphen_values = true,
+          help = "The station's own position, to show the range and bearing to the rover")]
+    pub station: Option<String>,
+
+    #[command(subcommand)]
+    pub subcommand: Option<Command>,
+}
+
+// with no subcommand the station listens to the radio as usual
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Feed frames from a capture file through the station instead of listening to the radio")]
+    Replay {
+        #[arg(value_name = "PATH", help = "Capture file: one hex frame per line, or raw 64 byte frames back to back")]
+        path: PathBuf,
+    },
+    #[command(about = "Check the radio over without the rover: set it up, transmit a Ping and listen for a while")]
+    Selftest {
+        #[arg(long, value_name = "SECS", default_value_t = 5, help = "How long to listen for")]
+        listen: u64,
+    },
+    #[command(about = "Set the radio up and print the value of every register, to compare with the rover's")]
+    DumpRegisters,
+    // for checking the outputs over, so it's left out of --help
+    #[command(hide = true, about = "Run made up telemetry through the station instead of listening to the radio")]
+    InjectTelemetry,
+}
+
+impl Args {
+    // load the config file and apply these options on top of it
+    pub fn config(&self) -> Result<Config> {
+        let mut config = config::load(&self.config)?;
+        if let Some(frequency) = self.frequency {
+            config.radio.frequency_hz = frequency * 1e6;
+        }
+        if let Some(power) = self.power {
+            config.radio.power_level = power;
+        }
+        if let Some(bit_rate) = self.bit_rate {
+            config.radio.bit_rate = bit_rate;
+        }
+        if self.no_encryption {
+            config.radio.use_encryption = false;
+        }
+        if let Some(key_file) = &self.key_file {
+            config.radio.key_path = key_file.clone();
+        }
+        if self.rover.is_some() {
+            config.radio.rover_address = self.rover;
+        }
+        if self.addressed {
+            config.radio.send_mode = SendMode::Addressed;
+        }
+        if let Some(broker) = &self.mqtt {
+            let mqtt = config.mqtt.get_or_insert_with(MqttConfig::default);
+            let mut parts = broker.splitn(2, ':');
+            mqtt.host = parts.next().unwrap_or_default().into();
+            if let Some(port) = parts.next() {
+                mqtt.port = port.parse().chain_err(|| format!("bad MQTT port in {}", broker))?;
+            }
+        }
+        if let (Some(mqtt), Some(topic)) = (&mut config.mqtt, &self.mqtt_topic) {
+            mqtt.topic = topic.clone();
+        }
+        // credentials can come from the environment so they needn't be in a
+        // file or show up in the process list
+        if let (Some(mqtt), Ok(username), Ok(password)) = (&mut config.mqtt, env::var("MQTT_USERNAME"), env::var("MQTT_PASSWORD")) {
+            mqtt.username = Some(username);
+            mqtt.password = Some(password);
+        }
+        if let Some(port) = self.http {
+            config.http_port = Some(port);
+        }
+        if self.ping.is_some() {
+            config.ping_interval_secs = self.ping;
+        }
+        if let Some(station) = &self.station {
+            let bad_position = || format!("bad station position {}, expected LAT,LON", station);
+            let (lat, lon) = station.split_once(',').ok_or_else(bad_position)?;
+            config.station = Some(StationConfig { lat: lat.trim().parse().chain_err(bad_position)?,
+                                                  lon: lon.trim().parse().chain_err(bad_position)? });
+        }
+        Ok(config)
+    }
+}
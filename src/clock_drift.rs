@@ -0,0 +1,68 @@
+// compares the time in the rover's telemetry with the station's clock, so a
+// rover clock that's wrong shows up before it throws off anything timed.
+// only the time of day is compared: the date hardly matters over a mission,
+// and a rover that hasn't had its date set yet can still have a good clock.
+// the difference is taken the short way round midnight, so a rover at
+// 23:59:59 with the station at 00:00:01 is 2 s behind, not 23:59:58 ahead.
+
+use crate::messages::RoverTimestamp;
+use chrono::{ DateTime, Timelike, Utc };
+use log::{ info, warn };
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub struct ClockWatch {
+    limit_secs: u64,
+    drifting: bool,
+}
+
+impl ClockWatch {
+    pub fn new(limit_secs: u64) -> ClockWatch {
+        ClockWatch { limit_secs, drifting: false }
+    }
+
+    // warn when the rover's clock first goes more than the limit out, and say
+    // so when it's back; returns the drift either way
+    pub fn check(&mut self, rover: &RoverTimestamp, station: DateTime<Utc>) -> i64 {
+        let drift = drift_secs(rover, station);
+        let drifting = drift.unsigned_abs() > self.limit_secs;
+        if drifting && !self.drifting {
+            warn!("ROVER CLOCK DRIFT: the rover's clock is {} s {} the station's", drift.abs(),
+                  if drift > 0 { "ahead of" } else { "behind" });
+        } else if !drifting && self.drifting {
+            info!("Rover clock back within {} s of the station's ({:+} s)", self.limit_secs, drift);
+        }
+        self.drifting = drifting;
+        drift
+    }
+}
+
+// seconds the rover is ahead of the station (negative if it's behind), between
+// -12 and +12 hours
+pub fn drift_secs(rover: &RoverTimestamp, station: DateTime<Utc>) -> i64 {
+    let rover = i64::from(rover.hour) * 3600 + i64::from(rover.minute) * 60 + i64::from(rover.second);
+    let station = i64::from(station.num_seconds_from_midnight());
+    let drift = (rover - station).rem_euclid(SECONDS_PER_DAY);
+    if drift > SECONDS_PER_DAY / 2 { drift - SECONDS_PER_DAY } else { drift }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn drift_goes_the_short_way_round_midnight() {
+        let at = |hour, minute, second| RoverTimestamp { year: 21, month: 6, day: 14, hour, minute, second };
+        let station = Utc.with_ymd_and_hms(2021, 6, 15, 0, 0, 1).unwrap();
+        assert_eq!(drift_secs(&at(23, 59, 59), station), -2);
+        assert_eq!(drift_secs(&at(0, 0, 31), station), 30);
+        let station = Utc.with_ymd_and_hms(2021, 6, 14, 23, 59, 58).unwrap();
+        assert_eq!(drift_secs(&at(0, 0, 3), station), 5);
+        let mut watch = ClockWatch::new(10);
+        assert_eq!(watch.check(&at(23, 59, 20), station), -38);
+        assert!(watch.drifting);
+        watch.check(&at(23, 59, 55), station);
+        assert!(!watch.drifting);
+    }
+}
@@ -0,0 +1,131 @@
+// commands staged ahead of time, to be sent to the rover in one go the next
+// time it says it's ready for them (see command_session.rs), so a multi-step
+// operation goes the same way every time instead of being typed in by hand.
+// they usually come from a script file: one command per line, with blank
+// lines and lines starting with # ignored.
+
+use crate::command_session::CommandSession;
+use crate::errors::*;
+use crate::link_stats::LinkStats;
+use crate::radio::{ RadioConfig, RadioLink };
+use log::info;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: VecDeque<(usize, String)>,  // script line number, command
+}
+
+impl CommandQueue {
+    pub fn new() -> CommandQueue {
+        Default::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CommandQueue> {
+        let path = path.as_ref();
+        let script = fs::read_to_string(path).chain_err(|| format!("reading command script {}", path.display()))?;
+        Ok(CommandQueue::parse(&script))
+    }
+
+    fn parse(script: &str) -> CommandQueue {
+        let mut queue = CommandQueue::new();
+        for (number, line) in script.lines().enumerate() {
+            let command = line.trim();
+            if !command.is_empty() && !command.starts_with('#') {
+                queue.push(number + 1, command);
+            }
+        }
+        queue
+    }
+
+    fn push(&mut self, line: usize, command: &str) {
+        self.commands.push_back((line, command.into()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    // send everything in the queue as one command sequence in session, which
+    // the rover must already be ready for, waiting for each command to be
+    // acked before sending the next. sequence_complete goes on the last one. a
+    // command that isn't acked stops the sequence there and throws away the
+    // rest, since running them without it could leave the rover anywhere. the
+    // error names the script line of the command that failed.
+    pub fn send<R: RadioLink>(&mut self, session: &mut CommandSession, rfm: &mut R, config: &RadioConfig,
+                              stats: &mut LinkStats) -> Result<()> {
+        let total = self.commands.len();
+        while let Some((line, command)) = self.commands.pop_front() {
+            let number = total - self.commands.len();
+            if let Err(e) = session.send_command(rfm, config, stats, &command, self.commands.is_empty()) {
+                self.commands.clear();
+                let failed = format!("line {} ({}) failed; the rest of the script was not sent", line, command);
+                // still a radio error if it was one, so the main loop can deal with the radio
+                return match e {
+                    Error(ErrorKind::RadioError(_), _) => Err(e).chain_err(|| ErrorKind::RadioError(failed)),
+                    e => Err(e).chain_err(|| ErrorKind::SendError(failed))
+                };
+            }
+            info!("Command {} of {} (line {}) acked: {}", number, total, line, command);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::RoverMessage;
+    use crate::messages::tests::MockRadio;
+
+    fn ack(ack: bool) -> RoverMessage {
+        RoverMessage::CommandAck { timestamp: Default::default(), ack }
+    }
+
+    // a radio whose rover has already said it's ready
+    fn ready_radio() -> MockRadio {
+        let mut radio = MockRadio::default();
+        radio.queue_sequence(&RoverMessage::CommandReady { timestamp: Default::default(), ready: true }, 0);
+        radio
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let queue = CommandQueue::parse("# square\nFORWARD 10\n\n  TURN 90  \n#STOP\n");
+        assert_eq!(queue.commands, [(2, String::from("FORWARD 10")), (4, String::from("TURN 90"))]);
+    }
+
+    #[test]
+    fn last_command_completes_the_sequence() {
+        let mut queue = CommandQueue::parse("FORWARD 10\nSTOP");
+        let mut radio = ready_radio();
+        radio.queue_sequence(&ack(true), 0);
+        radio.queue_sequence(&ack(true), 1);
+        CommandSession::new().run_session(&mut radio, &RadioConfig::default(), &mut LinkStats::new(), &mut queue).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!(radio.sent.len(), 2);
+        // sequence_complete is the first byte after the length, header, message
+        // id, version and timestamp
+        assert_eq!(radio.sent[0][13], 0);
+        assert_eq!(radio.sent[1][13], 1);
+    }
+
+    #[test]
+    fn refused_command_halts_the_queue() {
+        let mut queue = CommandQueue::parse("FORWARD 10\n\nTURN 90\nSTOP");
+        let mut radio = ready_radio();
+        radio.queue_sequence(&ack(true), 0);
+        // refused on every attempt
+        for sequence in 1..=3 {
+            radio.queue_sequence(&ack(false), sequence);
+        }
+        let e = CommandSession::new().run_session(&mut radio, &RadioConfig::default(), &mut LinkStats::new(), &mut queue)
+            .unwrap_err();
+        assert!(e.to_string().contains("line 3 (TURN 90) failed"));
+        assert!(queue.is_empty());
+        // the first command, then the second three times; STOP never goes out
+        assert_eq!(radio.sent.len(), 4);
+    }
+}
@@ -0,0 +1,113 @@
+// the command handshake. the station asks for command mode by setting
+// command_waiting in a TelemetryAck; the rover answers with CommandReady and
+// then waits for CommandMessages, acking each one, until one arrives with
+// sequence_complete set, which hands it back to normal telemetry. a
+// CommandSession tracks where we are in that, and refuses to send commands
+// the rover isn't ready for.
+
+use crate::command_queue::CommandQueue;
+use crate::errors::*;
+use crate::link_stats::LinkStats;
+use crate::messages::*;
+use crate::radio::{ RadioConfig, RadioLink };
+
+const COMMAND_READY_TIMEOUT: u64 = 5000;  // millis to wait for CommandReady after asking for command mode
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SessionState {
+    #[default]
+    Idle,           // the rover is sending telemetry
+    AwaitingReady,  // command mode has been asked for, waiting for CommandReady
+    Sending,        // the rover is taking commands
+    Complete,       // sequence_complete has been acked; the rover is back to telemetry
+}
+
+#[derive(Default)]
+pub struct CommandSession {
+    state: SessionState,
+}
+
+impl CommandSession {
+    pub fn new() -> CommandSession {
+        Default::default()
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    // wait for the rover's CommandReady. anything going wrong, including the
+    // rover saying it isn't ready, puts the session back to Idle.
+    pub fn await_ready<R: RadioLink>(&mut self, rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats) -> Result<()> {
+        self.state = SessionState::AwaitingReady;
+        let mut ready = RoverMessage::empty_command_ready();
+        if let Err(e) = ready.receive(rfm, config, stats, COMMAND_READY_TIMEOUT) {
+            self.state = SessionState::Idle;
+            return Err(e);
+        }
+        if let RoverMessage::CommandReady { ready: false, .. } = ready {
+            self.state = SessionState::Idle;
+            return Err(ErrorKind::ReceiveError("rover is not ready for commands".into()).into());
+        }
+        self.state = SessionState::Sending;
+        Ok(())
+    }
+
+    // send one command and wait for its ack (each of those has its own
+    // timeout, see radio::Timing). once the sequence_complete one is acked the
+    // session is over. a command that fails leaves the session Sending, since
+    // the rover is still waiting for one.
+    pub fn send_command<R: RadioLink>(&mut self, rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats,
+                                      command: &str, sequence_complete: bool) -> Result<()> {
+        if self.state != SessionState::Sending {
+            return Err(ErrorKind::SendError(format!("rover isn't ready for commands ({:?})", self.state)).into());
+        }
+        RoverMessage::command(sequence_complete, command).send(rfm, config, stats)?;
+        if sequence_complete {
+            self.state = SessionState::Complete;
+        }
+        Ok(())
+    }
+
+    // the whole handshake for a script of commands: wait for CommandReady,
+    // then send them all
+    pub fn run_session<R: RadioLink>(&mut self, rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats,
+                                     commands: &mut CommandQueue) -> Result<()> {
+        self.await_ready(rfm, config, stats)?;
+        commands.send(self, rfm, config, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::tests::MockRadio;
+
+    #[test]
+    fn session_follows_the_handshake() {
+        let mut radio = MockRadio::default();
+        radio.queue_sequence(&RoverMessage::CommandReady { timestamp: Default::default(), ready: true }, 0);
+        radio.queue_sequence(&RoverMessage::CommandAck { timestamp: Default::default(), ack: true }, 0);
+        radio.queue_sequence(&RoverMessage::CommandAck { timestamp: Default::default(), ack: true }, 1);
+        let (config, mut stats) = (RadioConfig::default(), LinkStats::new());
+        let mut session = CommandSession::new();
+        assert!(session.send_command(&mut radio, &config, &mut stats, "STOP", true).is_err());
+        assert!(radio.sent.is_empty());
+        session.await_ready(&mut radio, &config, &mut stats).unwrap();
+        assert_eq!(session.state(), SessionState::Sending);
+        session.send_command(&mut radio, &config, &mut stats, "FORWARD 10", false).unwrap();
+        assert_eq!(session.state(), SessionState::Sending);
+        session.send_command(&mut radio, &config, &mut stats, "STOP", true).unwrap();
+        assert_eq!(session.state(), SessionState::Complete);
+        assert_eq!(radio.sent.len(), 2);
+    }
+
+    #[test]
+    fn no_ready_goes_back_to_idle() {
+        let mut radio = MockRadio::default();
+        radio.queue_sequence(&RoverMessage::CommandReady { timestamp: Default::default(), ready: false }, 0);
+        let mut session = CommandSession::new();
+        assert!(session.await_ready(&mut radio, &RadioConfig::default(), &mut LinkStats::new()).is_err());
+        assert_eq!(session.state(), SessionState::Idle);
+    }
+}
@@ -0,0 +1,179 @@
+// everything tunable about the station, read from a TOML file so a deployment
+// doesn't need a long command line. every setting is optional; an empty or
+// missing file gives the same behavior as no file at all. see README.md for
+// the schema.
+
+use crate::alerts::AlertConfig;
+use crate::display_template::DisplayConfig;
+use crate::errors::*;
+use crate::influx::InfluxConfig;
+use crate::memory_watch::MIN_TREND_FRAMES;
+use crate::messages::CoordinateFormat;
+use crate::mqtt::MqttConfig;
+use crate::radio::RadioConfig;
+use crate::scan::ScanConfig;
+use crate::serial_radio::SerialConfig;
+use crate::synthetic::SyntheticConfig;
+use crate::websocket::WebSocketConfig;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub radio: RadioConfig,
+    pub serial: Option<SerialConfig>,  // use a serial gateway instead of the bonnet, see serial_radio.rs
+    pub log: LogConfig,
+    pub display: DisplayConfig,    // what goes on the OLED
+    pub mqtt: Option<MqttConfig>,  // publishing is off unless there's an [mqtt] table
+    pub influxdb: Option<InfluxConfig>,  // and so is writing to InfluxDB without an [influxdb] table
+    pub http_port: Option<u16>,    // the HTTP server is off unless a port is given
+    pub websocket: Option<WebSocketConfig>,  // as is the WebSocket server without a [websocket] table
+    pub ping_interval_secs: Option<u64>,  // no pings unless an interval is given
+    pub station: Option<StationConfig>,   // without it there's no range and bearing to the rover
+    pub scan: ScanConfig,                 // where to look for the rover with --scan
+    pub synthetic: SyntheticConfig,       // the pretend rover for inject-telemetry
+    pub coordinate_format: CoordinateFormat,  // how positions are shown on the console
+    pub low_memory_bytes: Option<u16>,        // warn when the rover reports less free memory than this
+    pub memory_trend_frames: Option<usize>,   // warn when free memory has fallen over this many frames
+    pub alerts: Option<AlertConfig>,          // no bell or alert command without an [alerts] table
+    pub clock_drift_secs: Option<u64>,        // warn when the rover's clock is further than this from ours
+    pub record_interval_ms: Option<u64>,      // log and forward telemetry at most this often (every frame if not given)
+    pub watchdog_secs: Option<u64>,           // reset the radio after the receive loop hangs for longer than this
+}
+
+// where the station itself is, for the range and bearing to the rover
+#[derive(Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StationConfig {
+    pub lat: f64,  // decimal degrees
+    pub lon: f64,
+}
+
+// where received telemetry gets written
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub telemetry_path: PathBuf,  // CSV, appended to
+    pub gpx_path: PathBuf,        // the rover's track, written on shutdown
+    pub report_path: PathBuf,     // JSON summary of the mission, written on shutdown
+    pub signal_path: Option<PathBuf>,  // CSV of signal strength against position, for range testing
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            telemetry_path: PathBuf::from("telemetry.csv"),
+            gpx_path: PathBuf::from("track.gpx"),
+            report_path: PathBuf::from("mission.json"),
+            signal_path: None
+        }
+    }
+}
+
+// ~/.ground_control, home of the config and key files
+pub fn ground_control_dir() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    Path::new(&home).join(".ground_control")
+}
+
+// ~/.ground_control/ground_control.toml
+pub fn default_config_path() -> PathBuf {
+    ground_control_dir().join("ground_control.toml")
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
+    let path = path.as_ref();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(e).chain_err(|| format!("reading config file {}", path.display()))
+    };
+    let config: Config = toml::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+    config.check().map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(config)
+}
+
+impl Config {
+    // settings that parse but make no sense
+    fn check(&self) -> Result<()> {
+        if let Some(frames) = self.memory_trend_frames {
+            if frames < MIN_TREND_FRAMES {
+                return Err(format!("memory_trend_frames must be at least {}, got {}", MIN_TREND_FRAMES, frames).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rppal::spi::{ Bus, SlaveSelect };
+
+    #[test]
+    fn empty_file_gives_defaults() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.radio.frequency_hz, RadioConfig::default().frequency_hz);
+        assert_eq!(config.log.telemetry_path, PathBuf::from("telemetry.csv"));
+        assert!(config.mqtt.is_none());
+        assert!(config.http_port.is_none());
+    }
+
+    #[test]
+    fn settings_override_defaults() {
+        let config: Config = toml::from_str(r#"
+            http_port = 8080
+            coordinate_format = "dms"
+
+            [radio]
+            frequency_hz = 868e6
+
+            [radio.pins]
+            spi_bus = 1
+            slave_select = 2
+
+            [radio.timing]
+            ack_timeout_ms = 3000
+
+            [mqtt]
+            host = "broker"
+
+            [station]
+            lat = 37.42
+            lon = -122.08
+        "#).unwrap();
+        assert_eq!(config.radio.frequency_hz, 868e6);
+        assert_eq!(config.radio.power_level, RadioConfig::default().power_level);
+        assert_eq!(config.radio.pins.spi_bus, Bus::Spi1);
+        assert_eq!(config.radio.pins.slave_select, SlaveSelect::Ss2);
+        assert_eq!(config.radio.pins.cs_pin, 7);
+        assert_eq!(config.radio.timing.ack_timeout_ms, 3000);
+        assert_eq!(config.radio.timing.msg_delay_ms, 100);
+        let mqtt = config.mqtt.unwrap();
+        assert_eq!(mqtt.host, "broker");
+        assert_eq!(mqtt.topic, "rover/telemetry");
+        assert_eq!(config.http_port, Some(8080));
+        assert_eq!(config.coordinate_format, CoordinateFormat::Dms);
+        let station = config.station.unwrap();
+        assert_eq!((station.lat, station.lon), (37.42, -122.08));
+    }
+
+    #[test]
+    fn memory_trend_needs_two_frames() {
+        for frames in 0..=1 {
+            let config: Config = toml::from_str(&format!("memory_trend_frames = {}", frames)).unwrap();
+            assert_eq!(config.check().unwrap_err().to_string(),
+                       format!("memory_trend_frames must be at least 2, got {}", frames));
+        }
+        let config: Config = toml::from_str("memory_trend_frames = 2").unwrap();
+        assert!(config.check().is_ok());
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(toml::from_str::<Config>("[radio]\nfrequency = 915").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+// a full screen view for an operator at a laptop: where the rover is and how
+// the link is doing stay put at the top, with everything the station logs
+// scrolling underneath, rather than it all scrolling off the console. the
+// main loop keeps the Dashboard up to date; a thread of its own redraws it a
+// few times a second and watches for q, or Ctrl-C (which the terminal no
+// longer turns into a signal once it's in raw mode), to stop the station.
+
+use crate::dead_reckoning::Estimate;
+use crate::errors::*;
+use crate::link_stats::LinkStats;
+use crate::messages::*;
+use chrono::Local;
+use log::{ LevelFilter, Log, Metadata, Record };
+use ratatui::crossterm::event::{ self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers };
+use ratatui::layout::{ Constraint, Layout };
+use ratatui::text::Line;
+use ratatui::widgets::{ Block, Paragraph };
+use ratatui::{ DefaultTerminal, Frame };
+use std::collections::VecDeque;
+use std::sync::{ Arc, Mutex, atomic::{ AtomicBool, Ordering } };
+use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, Instant };
+
+const EVENT_HISTORY: usize = 500;  // log lines kept for the event log
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+const STATUS_LINES: u16 = 8;
+
+#[derive(Default)]
+struct State {
+    showing: bool,                  // whether the dashboard is on the screen right now
+    telemetry: Option<RoverMessage>,
+    position: Option<String>,       // formatted the operator's way, with the estimate if there's no fix
+    last_packet: Option<Instant>,
+    station_rssi: Option<f32>,
+    rtt: Option<Duration>,
+    crc_failure_rate: Option<f32>,
+    events: VecDeque<String>,
+}
+
+#[derive(Clone)]
+pub struct Dashboard {
+    state: Arc<Mutex<State>>,
+    link_lost_secs: u64,
+}
+
+impl Dashboard {
+    pub fn new(link_lost_secs: u64) -> Dashboard {
+        Dashboard { state: Default::default(), link_lost_secs }
+    }
+
+    pub fn telemetry(&self, telemetry: &RoverMessage, estimate: Option<Estimate>, coordinates: CoordinateFormat) {
+        let position = match (telemetry, estimate) {
+            (RoverMessage::TelemetryMessage { location, .. }, _) if location.has_fix() =>
+                format!("{}, {}", location.format_lat(coordinates), location.format_long(coordinates)),
+            (_, Some(estimate)) => format!("{}, {} ESTIMATED, {} s since the last fix",
+                                           format_lat(estimate.lat, coordinates),
+                                           format_long(estimate.lon, coordinates), estimate.since_fix_secs),
+            _ => String::from("no fix")
+        };
+        self.event(telemetry.summary(coordinates).to_string());
+        self.update(|state| {
+            state.telemetry = Some(telemetry.clone());
+            state.position = Some(position);
+        });
+    }
+
+    pub fn link(&self, stats: &LinkStats, last_packet: Instant) {
+        self.update(|state| {
+            state.last_packet = Some(last_packet);
+            state.station_rssi = stats.mean_rssi();
+            state.rtt = stats.rtt();
+            state.crc_failure_rate = stats.crc_failure_rate();
+        });
+    }
+
+    // a line for the event log, stamped with the time
+    pub fn event(&self, line: String) {
+        self.update(|state| {
+            if state.events.len() == EVENT_HISTORY {
+                state.events.pop_front();
+            }
+            state.events.push_back(format!("{} {}", Local::now().format("%H:%M:%S"), line));
+        });
+    }
+
+    // take over the terminal until running goes false or the Screen is dropped
+    pub fn show(&self, running: Arc<AtomicBool>) -> Result<Screen> {
+        let terminal = ratatui::try_init().chain_err(|| "setting up the terminal for the dashboard")?;
+        self.update(|state| state.showing = true);
+        let dashboard = self.clone();
+        let stop = running.clone();
+        let drawing = thread::spawn(move || dashboard.draw_until_stopped(terminal, &stop));
+        Ok(Screen { running, drawing: Some(drawing) })
+    }
+
+    fn showing(&self) -> bool {
+        self.state.lock().is_ok_and(|state| state.showing)
+    }
+
+    // a poisoned lock just means the dashboard stops changing
+    fn update(&self, change: impl FnOnce(&mut State)) {
+        if let Ok(mut state) = self.state.lock() {
+            change(&mut state);
+        }
+    }
+
+    fn draw_until_stopped(&self, mut terminal: DefaultTerminal, running: &AtomicBool) {
+        while running.load(Ordering::SeqCst) {
+            if terminal.draw(|frame| self.draw(frame)).is_err() {
+                break;
+            }
+            if let Ok(true) = event::poll(REDRAW_INTERVAL) {
+                if let Ok(Event::Key(key)) = event::read() {
+                    if quits(&key) {
+                        running.store(false, Ordering::SeqCst);
+                    }
+                }
+            }
+        }
+        let _ = ratatui::try_restore();
+        self.update(|state| state.showing = false);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return
+        };
+        let [status, events] = Layout::vertical([Constraint::Length(STATUS_LINES + 2), Constraint::Min(3)]).areas(frame.area());
+        let lines: Vec<Line> = status_lines(&state, self.link_lost_secs).into_iter().map(Line::from).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Rover ")), status);
+        // the newest at the bottom, as many as fit
+        let shown = events.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = state.events.iter().skip(state.events.len().saturating_sub(shown))
+            .map(|line| Line::from(line.as_str())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Events (q to quit) ")), events);
+    }
+}
+
+fn quits(key: &KeyEvent) -> bool {
+    key.kind == KeyEventKind::Press
+        && (key.code == KeyCode::Char('q')
+            || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)))
+}
+
+fn status_lines(state: &State, link_lost_secs: u64) -> Vec<String> {
+    let unknown = || String::from("-");
+    let mut lines = vec![format!("Position   {}", state.position.clone().unwrap_or_else(|| String::from("waiting for telemetry")))];
+    match &state.telemetry {
+        Some(RoverMessage::TelemetryMessage { location, signal_strength, free_memory, battery_millivolts, status, .. }) => {
+            lines.push(format!("Speed      {:.1} m/s, heading {} ({})", location.gps_speed, location.gps_hdg, location.compass_point()));
+            lines.push(format!("Sats       {}", location.gps_sats));
+            lines.push(format!("Battery    {} mV, {} bytes free", battery_millivolts, free_memory));
+            lines.push(format!("Status     {}", status));
+            lines.push(format!("RSSI       rover {} dBm, station {}", signal_strength,
+                               state.station_rssi.map_or_else(unknown, |rssi| format!("{:.0} dBm", rssi))));
+        },
+        _ => lines.extend(["Speed", "Sats", "Battery", "Status", "RSSI"].iter().map(|field| format!("{:<10} -", field)))
+    }
+    lines.push(format!("RTT        {}, CRC failures {}",
+                       state.rtt.map_or_else(unknown, |rtt| format!("{} ms", rtt.as_millis())),
+                       state.crc_failure_rate.map_or_else(unknown, |rate| format!("{:.1}%", rate * 100.0))));
+    lines.push(match state.last_packet.map(|last| last.elapsed().as_secs()) {
+        Some(secs) if secs > link_lost_secs => format!("Link       LOST, nothing for {} s", secs),
+        Some(secs) if state.telemetry.is_some() => format!("Link       OK, last packet {} s ago", secs),
+        _ => String::from("Link       waiting for the rover")
+    });
+    lines
+}
+
+// while the dashboard is up, log messages go in its event log instead of over
+// the top of it. the rest of the time (before it's shown, and after it's
+// closed) they go to stderr as usual.
+struct EventLogger {
+    stderr: env_logger::Logger,
+    dashboard: Dashboard,
+}
+
+impl Log for EventLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        if self.dashboard.showing() {
+            self.dashboard.event(format!("{:<5} {}", record.level(), record.args()));
+        } else {
+            self.stderr.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+    }
+}
+
+pub fn init_logging(builder: &mut env_logger::Builder, dashboard: &Dashboard) {
+    let stderr = builder.build();
+    let level: LevelFilter = stderr.filter();
+    if log::set_boxed_logger(Box::new(EventLogger { stderr, dashboard: dashboard.clone() })).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+// the dashboard being on the screen; dropping it puts the terminal back
+pub struct Screen {
+    running: Arc<AtomicBool>,
+    drawing: Option<JoinHandle<()>>,
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(drawing) = self.drawing.take() {
+            let _ = drawing.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_shows_the_latest_telemetry() {
+        let dashboard = Dashboard::new(30);
+        let telemetry = RoverMessage::TelemetryMessage {
+            timestamp: Default::default(),
+            location: RoverLocData { gps_lat: 37.5, gps_long: -122.25, gps_speed: 1.5, gps_sats: 8, gps_hdg: 270, ..Default::default() },
+            signal_strength: -72,
+            free_memory: 1024,
+            battery_millivolts: 7400,
+            status: "OK".into()
+        };
+        dashboard.telemetry(&telemetry, None, CoordinateFormat::Decimal);
+        let mut stats = LinkStats::new();
+        stats.record_rssi(-65.0);
+        dashboard.link(&stats, Instant::now());
+        let state = dashboard.state.lock().unwrap();
+        let lines = status_lines(&state, 30);
+        assert_eq!(lines[0], "Position   37.50000, -122.25000");
+        assert_eq!(lines[1], "Speed      1.5 m/s, heading 270 (W)");
+        assert_eq!(lines[5], "RSSI       rover -72 dBm, station -65 dBm");
+        assert_eq!(lines[7], "Link       OK, last packet 0 s ago");
+        assert_eq!(lines.len(), STATUS_LINES as usize);
+        // and the summary went in the event log
+        assert!(state.events[0].contains("TELEM"));
+    }
+}
@@ -0,0 +1,88 @@
+// a rough idea of where the rover is while it has no GPS fix: carry on from
+// the last fix at the speed and heading it reports, for however long passed
+// between its telemetry timestamps. it's only a guess, and gets worse the
+// longer the fix is lost, so it's always shown marked as an estimate.
+// gps_speed is taken to be in meters per second.
+
+use crate::messages::*;
+use chrono::{ DateTime, Utc };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Estimate {
+    pub lat: f64,
+    pub lon: f64,
+    pub since_fix_secs: i64,
+}
+
+struct Position {
+    lat: f64,
+    lon: f64,
+    time: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct DeadReckoning {
+    last: Option<Position>,            // the last fix, or the estimate carried on from it
+    last_fix: Option<DateTime<Utc>>,
+}
+
+impl DeadReckoning {
+    pub fn new() -> DeadReckoning {
+        Default::default()
+    }
+
+    // take the position from a telemetry frame, returning an estimate if it
+    // doesn't have a fix. there's nothing to estimate from until the rover
+    // has had a fix at least once.
+    pub fn update(&mut self, timestamp: &RoverTimestamp, location: &RoverLocData) -> Option<Estimate> {
+        let time = timestamp.to_datetime()?;
+        if location.has_fix() && location.gps_sats > 0 {
+            self.last = Some(Position { lat: location.gps_lat.into(), lon: location.gps_long.into(), time });
+            self.last_fix = Some(time);
+            return None;
+        }
+        let last = self.last.as_mut()?;
+        let secs = ((time - last.time).num_milliseconds() as f64 / 1000.0).max(0.0);
+        let (lat, lon) = destination(last.lat, last.lon, location.gps_hdg.into(), f64::from(location.gps_speed) * secs);
+        *last = Position { lat, lon, time };
+        Some(Estimate { lat, lon, since_fix_secs: self.last_fix.map_or(0, |fix| (time - fix).num_seconds()) })
+    }
+}
+
+// where you end up going distance meters from lat/lon on the given bearing
+// (degrees clockwise from true north), along a great circle
+pub fn destination(lat: f64, lon: f64, bearing: f64, distance: f64) -> (f64, f64) {
+    let (lat1, lon1, bearing) = (lat.to_radians(), lon.to_radians(), bearing.to_radians());
+    let angle = distance / EARTH_RADIUS_M;
+    let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * bearing.cos()).asin();
+    let lon2 = lon1 + (bearing.sin() * angle.sin() * lat1.cos()).atan2(angle.cos() - lat1.sin() * lat2.sin());
+    (lat2.to_degrees(), (lon2.to_degrees() + 540.0) % 360.0 - 180.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(second: u8) -> RoverTimestamp {
+        RoverTimestamp { year: 21, month: 6, day: 14, hour: 12, minute: 0, second }
+    }
+
+    #[test]
+    fn estimate_carries_on_from_the_last_fix() {
+        let mut reckoning = DeadReckoning::new();
+        let lost = RoverLocData { gps_speed: 10.0, gps_hdg: 90, gps_sats: 0, ..Default::default() };
+        // nothing to go on yet
+        assert_eq!(reckoning.update(&at(0), &lost), None);
+        let fix = RoverLocData { gps_lat: 10.0, gps_long: 20.0, gps_sats: 6, ..Default::default() };
+        assert_eq!(reckoning.update(&at(0), &fix), None);
+        reckoning.update(&at(30), &lost).unwrap();
+        let estimate = reckoning.update(&at(50), &lost).unwrap();
+        assert_eq!(estimate.since_fix_secs, 50);
+        // 500m east of the fix
+        let (distance, bearing) = fix.distance_bearing_from(estimate.lat, estimate.lon);
+        assert!((distance - 500.0).abs() < 1.0);
+        assert!((bearing - 270.0).abs() < 0.1);
+        // a fix again ends the estimate
+        assert_eq!(reckoning.update(&at(55), &fix), None);
+    }
+}
@@ -0,0 +1,119 @@
+// the OLED in graphics mode, for telling how the rover's doing at a glance
+// from across the room: a compass needle for its heading on the left, bars
+// for the signal strength on the right, and the telemetry rows from the
+// display template squeezed in between, with the link status underneath.
+// the heading is the GPS course over the ground, since that's the only one
+// the rover sends, so the needle only means something while it's moving.
+//
+//    ,-.   row 1        |
+//   ( / )  row 2      | |
+//    `-'            | | |
+//          link   | | | |
+
+use embedded_graphics::{
+    fonts::{ Font6x8, Text },
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{ Circle, Line, Rectangle },
+    style::{ PrimitiveStyle, TextStyle },
+};
+
+const HEIGHT: i32 = 32;
+const COMPASS_CENTER: Point = Point::new(15, 15);
+const COMPASS_RADIUS: u32 = 14;
+const NEEDLE_LENGTH: f32 = 11.0;
+const TEXT_LEFT: i32 = 34;
+const TEXT_COLUMNS: usize = 12;  // between the compass and the bars in the 6x8 font
+const TEXT_ROWS: usize = 4;
+const TEXT_ROW_HEIGHT: i32 = 8;
+const BARS_LEFT: i32 = 109;
+const BAR_WIDTH: i32 = 4;
+const BAR_STEP: i32 = 5;
+const BAR_THRESHOLDS_DBM: [f32; 4] = [-90.0, -80.0, -70.0, -60.0];  // one more bar lit at each
+
+#[derive(Default)]
+pub struct Gauges {
+    heading: Option<u16>,  // degrees, None until the first telemetry
+    rssi: Option<f32>,     // dBm, the station's mean over recent packets
+    rows: Vec<String>,     // the telemetry rows from the display template
+    link: String,
+}
+
+impl Gauges {
+    pub fn telemetry(&mut self, heading: u16, rows: Vec<String>) {
+        self.heading = Some(heading);
+        self.rows = rows;
+    }
+
+    pub fn rssi(&mut self, rssi: f32) {
+        self.rssi = Some(rssi);
+    }
+
+    // whether the status has changed
+    pub fn link(&mut self, status: String) -> bool {
+        let changed = status != self.link;
+        self.link = status;
+        changed
+    }
+
+    pub fn draw<D: DrawTarget<BinaryColor>>(&self, target: &mut D) -> Result<(), D::Error> {
+        let stroke = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+        let text = TextStyle::new(Font6x8, BinaryColor::On);
+        Circle::new(COMPASS_CENTER, COMPASS_RADIUS).into_styled(stroke).draw(target)?;
+        if let Some(heading) = self.heading {
+            Line::new(COMPASS_CENTER, needle_end(heading)).into_styled(stroke).draw(target)?;
+        }
+        // the template's rows from the top, the link status on the bottom
+        let rows = self.rows.iter().take(TEXT_ROWS - 1).enumerate().chain(std::iter::once((TEXT_ROWS - 1, &self.link)));
+        for (row, line) in rows {
+            let line: String = line.chars().take(TEXT_COLUMNS).collect();
+            Text::new(&line, Point::new(TEXT_LEFT, row as i32 * TEXT_ROW_HEIGHT)).into_styled(text).draw(target)?;
+        }
+        // each bar a quarter of the height taller than the last, lit ones filled in
+        let lit = self.rssi.map_or(0, signal_bars);
+        for bar in 0..BAR_THRESHOLDS_DBM.len() as i32 {
+            let left = BARS_LEFT + bar * BAR_STEP;
+            let top = HEIGHT - (bar + 1) * HEIGHT / 4;
+            let style = if bar < i32::from(lit) { PrimitiveStyle::with_fill(BinaryColor::On) } else { stroke };
+            Rectangle::new(Point::new(left, top), Point::new(left + BAR_WIDTH - 1, HEIGHT - 1)).into_styled(style).draw(target)?;
+        }
+        Ok(())
+    }
+}
+
+// plain lines of text, for the welcome and shutdown messages
+pub fn draw_message<D: DrawTarget<BinaryColor>>(target: &mut D, message: &str) -> Result<(), D::Error> {
+    let text = TextStyle::new(Font6x8, BinaryColor::On);
+    for (row, line) in message.lines().take(TEXT_ROWS).enumerate() {
+        Text::new(line, Point::new(0, row as i32 * TEXT_ROW_HEIGHT)).into_styled(text).draw(target)?;
+    }
+    Ok(())
+}
+
+// how many of the bars to light for a signal strength
+fn signal_bars(rssi: f32) -> u8 {
+    BAR_THRESHOLDS_DBM.iter().filter(|threshold| rssi >= **threshold).count() as u8
+}
+
+// the tip of the compass needle: north is straight up, and the screen's y
+// runs downwards
+fn needle_end(heading: u16) -> Point {
+    let radians = f32::from(heading % 360).to_radians();
+    Point::new(COMPASS_CENTER.x + (NEEDLE_LENGTH * radians.sin()).round() as i32,
+               COMPASS_CENTER.y - (NEEDLE_LENGTH * radians.cos()).round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gauges_point_the_right_way() {
+        assert_eq!(needle_end(0), Point::new(15, 4));
+        assert_eq!(needle_end(90), Point::new(26, 15));
+        assert_eq!(needle_end(180), Point::new(15, 26));
+        assert_eq!(needle_end(630), Point::new(4, 15));
+        assert_eq!([-100.0, -90.0, -75.0, -61.0, -40.0].iter().map(|rssi| signal_bars(*rssi)).collect::<Vec<_>>(),
+                   vec![0, 1, 2, 3, 4]);
+    }
+}
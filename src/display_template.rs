@@ -0,0 +1,159 @@
+// what the OLED shows, so each deployment can put up whatever it watches
+// most. the screen is a 16x4 character terminal and the bottom two rows are
+// always the signal strength and the link status, which leaves the top two
+// for telemetry. fields go in braces, e.g. "Sig {rssi} Sats {sats}", and
+// anything past the 16th column is cut off.
+
+use crate::errors::*;
+use crate::memory_watch::MemoryState;
+use crate::messages::*;
+use serde::Deserialize;
+
+const TELEMETRY_ROWS: usize = 2;
+const WELCOME_ROWS: usize = 4;
+const FIELDS: [&str; 11] = ["rssi", "sats", "status", "lat", "lon", "alt", "speed", "hdg", "compass", "batt", "mem"];
+
+// text is the OLED as a 16x4 character terminal; graphics adds a compass
+// needle and signal bars (see display_graphics.rs), with the telemetry rows
+// cut down to 12 columns to fit between them
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayMode {
+    #[default]
+    Text,
+    Graphics,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub mode: DisplayMode,
+    pub welcome: String,         // shown until the first packet; {version} is the station's version
+    pub telemetry: Vec<String>,  // one template per row, at most TELEMETRY_ROWS of them
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            mode: DisplayMode::Text,
+            welcome: String::from("Rover Ground\nControl v{version}"),
+            telemetry: vec![String::from("Sig {rssi} Sats {sats}"), String::from("{status}")]
+        }
+    }
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Field(&'a str),
+}
+
+impl DisplayConfig {
+    // catch a bad template at startup rather than showing it garbled
+    pub fn check(&self) -> Result<()> {
+        if self.welcome.lines().count() > WELCOME_ROWS {
+            return Err(ErrorKind::DisplayError(format!("the welcome message can be at most {} lines", WELCOME_ROWS)).into());
+        }
+        if self.telemetry.len() > TELEMETRY_ROWS {
+            return Err(ErrorKind::DisplayError(format!("the telemetry template can be at most {} rows, got {}",
+                                                       TELEMETRY_ROWS, self.telemetry.len())).into());
+        }
+        for template in &self.telemetry {
+            for piece in pieces(template) {
+                if let Piece::Field(name) = piece {
+                    if !FIELDS.contains(&name) {
+                        return Err(ErrorKind::DisplayError(format!("unknown field {{{}}} in display template {:?}; \
+                                                                    the fields are {}", name, template, FIELDS.join(", "))).into());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn banner(&self) -> String {
+        self.welcome.replace("{version}", env!("CARGO_PKG_VERSION"))
+    }
+
+    // the telemetry rows, with the fields filled in. a rover short of memory
+    // gets flagged at the start of its status.
+    pub fn render(&self, telemetry: &RoverMessage, memory: MemoryState, coordinates: CoordinateFormat) -> Vec<String> {
+        let (location, signal_strength, free_memory, battery_millivolts, status) = match telemetry {
+            RoverMessage::TelemetryMessage { location, signal_strength, free_memory, battery_millivolts, status, .. } =>
+                (location, signal_strength, free_memory, battery_millivolts, status),
+            _ => return Vec::new()
+        };
+        let value = |name: &str| match name {
+            "rssi" => signal_strength.to_string(),
+            "sats" => location.gps_sats.to_string(),
+            "status" if memory == MemoryState::Ok => status.clone(),
+            "status" => format!("MEM! {}", status),
+            "lat" => location.format_lat(coordinates),
+            "lon" => location.format_long(coordinates),
+            "alt" => format!("{:.0}", location.gps_alt),
+            "speed" => format!("{:.1}", location.gps_speed),
+            "hdg" => location.gps_hdg.to_string(),
+            "compass" => location.compass_point().to_string(),
+            "batt" => battery_millivolts.to_string(),
+            "mem" => free_memory.to_string(),
+            _ => format!("{{{}}}", name)
+        };
+        self.telemetry.iter().map(|template| pieces(template).into_iter().map(|piece| match piece {
+            Piece::Text(text) => text.to_string(),
+            Piece::Field(name) => value(name)
+        }).collect()).collect()
+    }
+}
+
+// split a template into plain text and {field}s. a brace with no partner is
+// just text.
+fn pieces(template: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some((open, close)) = rest.find('{').and_then(|open| rest[open..].find('}').map(|close| (open, open + close))) {
+        if open > 0 {
+            pieces.push(Piece::Text(&rest[..open]));
+        }
+        pieces.push(Piece::Field(&rest[open + 1..close]));
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn telemetry() -> RoverMessage {
+        RoverMessage::TelemetryMessage {
+            timestamp: Default::default(),
+            location: RoverLocData { gps_lat: 37.5, gps_long: -122.25, gps_alt: 30.4, gps_sats: 8, gps_hdg: 270, ..Default::default() },
+            signal_strength: -72,
+            free_memory: 1024,
+            battery_millivolts: 7400,
+            status: "OK".into()
+        }
+    }
+
+    #[test]
+    fn default_layout() {
+        let config = DisplayConfig::default();
+        config.check().unwrap();
+        assert_eq!(config.render(&telemetry(), MemoryState::Ok, CoordinateFormat::Decimal), vec!["Sig -72 Sats 8", "OK"]);
+        assert_eq!(config.render(&telemetry(), MemoryState::Low, CoordinateFormat::Decimal)[1], "MEM! OK");
+        assert!(config.banner().starts_with("Rover Ground\nControl v0."));
+    }
+
+    #[test]
+    fn custom_layout() {
+        let config = DisplayConfig { telemetry: vec!["{batt}mV {alt}m {compass}".into(), "{lat}".into()], ..Default::default() };
+        config.check().unwrap();
+        assert_eq!(config.render(&telemetry(), MemoryState::Ok, CoordinateFormat::Decimal), vec!["7400mV 30m W", "37.50000"]);
+        let config = DisplayConfig { telemetry: vec!["{voltage}".into()], ..Default::default() };
+        assert!(config.check().is_err());
+        let config = DisplayConfig { telemetry: vec!["{rssi}".into(); 3], ..Default::default() };
+        assert!(config.check().is_err());
+    }
+}
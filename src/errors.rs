@@ -16,11 +16,19 @@ error_chain! {
             description("receive protocol error")
             display("receive protocol error: '{}'", t)
         }
+        SerializationError(t: String) {
+            description("serialization error")
+            display("serialization error: '{}'", t)
+        }
     }
     foreign_links {
+        Ctrlc(::ctrlc::Error);
+        Io(::std::io::Error);
+        Json(::serde_json::Error);
         RppalGpio(::rppal::gpio::Error);
         RppalI2c(::rppal::i2c::Error);
         RppalSpi(::rppal::spi::Error);
+        Rfm69(crate::radio::Rfm69Error);
         //Ssd1306 does not impl Display on its error types, so we can't include it here
         //bad, bad Ssd1306
         //Ssd1306(::ssd1306::mode::terminal::TerminalModeError);
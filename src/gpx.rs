@@ -0,0 +1,90 @@
+// accumulates the rover's GPS fixes and writes them out as a GPX 1.1 track,
+// so a mission can be opened in standard mapping tools
+
+use crate::errors::*;
+use crate::messages::*;
+use chrono::{ DateTime, SecondsFormat, Utc };
+use std::fs::File;
+use std::io::{ BufWriter, Write };
+use std::path::Path;
+
+struct GpxPoint {
+    lat: f32,
+    lon: f32,
+    ele: f32,
+    time: Option<DateTime<Utc>>,  // the rover's timestamp, if it was a real time
+}
+
+#[derive(Default)]
+pub struct GpxWriter {
+    points: Vec<GpxPoint>,
+}
+
+impl GpxWriter {
+    pub fn new() -> GpxWriter {
+        Default::default()
+    }
+
+    // remember one fix. the rover reports 0.0/0.0 until it has a GPS lock,
+    // so those are skipped rather than dropping a pin in the Gulf of Guinea.
+    // a rover whose date hasn't been set gets a point with no time, since
+    // mapping tools turn down a whole file over one bad time.
+    pub fn add_point(&mut self, location: &RoverLocData, timestamp: &RoverTimestamp) {
+        if !location.has_fix() { return; }
+        self.points.push(GpxPoint {
+            lat: location.gps_lat,
+            lon: location.gps_long,
+            ele: location.gps_alt,
+            time: timestamp.to_datetime()
+        });
+    }
+
+    // write everything collected so far to path as a single-segment track
+    pub fn finish<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut out = BufWriter::new(File::create(path).chain_err(|| format!("creating GPX track {}", path.display()))?);
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(out, "<gpx version=\"1.1\" creator=\"ground_control\" xmlns=\"http://www.topografix.com/GPX/1/1\">")?;
+        writeln!(out, "  <trk>")?;
+        writeln!(out, "    <name>Rover track</name>")?;
+        writeln!(out, "    <trkseg>")?;
+        for point in self.points.iter() {
+            let time = match point.time {
+                Some(time) => format!("<time>{}</time>", time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                None => String::new()
+            };
+            writeln!(out, "      <trkpt lat=\"{}\" lon=\"{}\"><ele>{}</ele>{}</trkpt>",
+                     point.lat, point.lon, point.ele, time)?;
+        }
+        writeln!(out, "    </trkseg>")?;
+        writeln!(out, "  </trk>")?;
+        writeln!(out, "</gpx>")?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn track_has_only_the_fixes() {
+        let mut gpx = GpxWriter::new();
+        let at = |day| RoverTimestamp { year: 21, month: 6, day, hour: 12, minute: 3, second: 45 };
+        gpx.add_point(&RoverLocData::default(), &at(14));  // no fix yet
+        gpx.add_point(&RoverLocData { gps_lat: 37.5, gps_long: -122.25, gps_alt: 30.0, gps_sats: 6, ..Default::default() }, &at(14));
+        // the rover's date isn't set
+        gpx.add_point(&RoverLocData { gps_lat: 37.75, gps_long: -122.5, gps_alt: 31.0, gps_sats: 6, ..Default::default() }, &at(0));
+        let path = std::env::temp_dir().join(format!("ground_control_track_{}.gpx", std::process::id()));
+        gpx.finish(&path).unwrap();
+        let xml = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let points: Vec<&str> = xml.lines().map(str::trim).filter(|line| line.starts_with("<trkpt")).collect();
+        assert_eq!(points, ["<trkpt lat=\"37.5\" lon=\"-122.25\"><ele>30</ele><time>2021-06-14T12:03:45Z</time></trkpt>",
+                            "<trkpt lat=\"37.75\" lon=\"-122.5\"><ele>31</ele></trkpt>"]);
+        assert!(xml.starts_with("<?xml"));
+        assert!(xml.trim_end().ends_with("</gpx>"));
+    }
+}
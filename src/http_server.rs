@@ -0,0 +1,82 @@
+// serves the most recent telemetry as JSON at /telemetry, for keeping an eye
+// on the rover from a laptop on the same network
+
+use crate::errors::*;
+use crate::messages::*;
+use log::warn;
+use std::sync::{ Arc, Mutex };
+use std::thread;
+use tiny_http::{ Header, Request, Response, Server };
+
+// the latest TelemetryMessage received, shared between the receive loop and
+// the server thread
+pub type LatestTelemetry = Arc<Mutex<Option<RoverMessage>>>;
+
+// start serving on the given port in a background thread
+pub fn start(port: u16, latest: LatestTelemetry) -> Result<()> {
+    let server = Server::http(("0.0.0.0", port))
+        .map_err(|e| format!("starting HTTP server on port {}: {}", port, e))?;
+    thread::spawn(move || serve(server, latest));
+    Ok(())
+}
+
+fn serve(server: Server, latest: LatestTelemetry) {
+    for request in server.incoming_requests() {
+        if let Err(e) = respond(request, &latest) {
+            warn!("Error answering HTTP request: {}", e);
+        }
+    }
+}
+
+fn respond(request: Request, latest: &LatestTelemetry) -> Result<()> {
+    if request.url() != "/telemetry" {
+        request.respond(Response::from_string("not found\n").with_status_code(404))?;
+        return Ok(());
+    }
+    // render while holding the lock, but don't hold it while writing to the network
+    let body = match &*latest.lock().map_err(|_| "telemetry lock poisoned")? {
+        Some(telemetry) => Some(serde_json::to_string(telemetry)?),
+        None => None
+    };
+    match body {
+        Some(body) => {
+            let json = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                .map_err(|_| "building Content-Type header")?;
+            request.respond(Response::from_string(body).with_header(json))?;
+        },
+        None => request.respond(Response::from_string("no telemetry received yet\n").with_status_code(503))?
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ Read, Write };
+    use std::net::TcpStream;
+
+    // the status line and body of a GET
+    fn get(port: u16, path: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "GET {} HTTP/1.0\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    #[test]
+    fn serves_the_latest_telemetry() {
+        let server = Server::http(("127.0.0.1", 0)).unwrap();
+        let port = server.server_addr().port();
+        let latest = LatestTelemetry::default();
+        let shared = latest.clone();
+        thread::spawn(move || serve(server, shared));
+        assert_eq!(get(port, "/telemetry").0, "HTTP/1.0 503 Service Unavailable");
+        *latest.lock().unwrap() = Some(RoverMessage::Pong { timestamp: RoverTimestamp { year: 21, month: 6, day: 14, hour: 12, minute: 3, second: 45 } });
+        let (status, body) = get(port, "/telemetry");
+        assert_eq!(status, "HTTP/1.0 200 OK");
+        assert!(body.contains("\"minute\":3"), "{}", body);
+        assert_eq!(get(port, "/").0, "HTTP/1.0 404 Not Found");
+    }
+}
@@ -0,0 +1,130 @@
+// writes received telemetry to InfluxDB in its line protocol, so the rover's
+// track and health end up next to everything else being graphed. points are
+// handed to a thread of their own that batches them up and POSTs them, so a
+// slow or dead database never holds up the receive loop.
+
+use crate::errors::*;
+use crate::messages::*;
+use log::warn;
+use serde::Deserialize;
+use std::sync::mpsc::{ self, Receiver, RecvTimeoutError, SyncSender };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+const MEASUREMENT: &str = "rover_telemetry";
+const QUEUE_SIZE: usize = 256;       // points waiting for the writer thread; newer ones are dropped once full
+const BATCH_WAIT: u64 = 10000;       // millis to wait for a batch to fill before writing what there is
+const WRITE_ATTEMPTS: u64 = 3;       // times to try writing a batch before dropping it
+const RETRY_DELAY: u64 = 2000;       // millis to back off before retrying a write (multiplied by the retry number)
+const WRITE_TIMEOUT: u64 = 10000;    // millis to wait for InfluxDB to answer
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfluxConfig {
+    pub url: String,            // the whole write endpoint, including the database (or org and bucket)
+    pub token: Option<String>,  // sent as "Authorization: Token ..." if set
+    pub batch_size: usize,      // points per write
+}
+
+impl Default for InfluxConfig {
+    fn default() -> Self {
+        Self {
+            url: String::from("http://localhost:8086/write?db=rover"),
+            token: None,
+            batch_size: 10
+        }
+    }
+}
+
+pub struct InfluxExporter {
+    points: SyncSender<String>,
+    rover_address: Option<u8>,  // the rover tag, if we're only listening to one
+}
+
+impl InfluxExporter {
+    pub fn new(config: &InfluxConfig, rover_address: Option<u8>) -> InfluxExporter {
+        let (points, queue) = mpsc::sync_channel(QUEUE_SIZE);
+        let (url, token, batch_size) = (config.url.clone(), config.token.clone(), config.batch_size.max(1));
+        thread::spawn(move || write_points(queue, &url, token.as_deref(), batch_size));
+        InfluxExporter { points, rover_address }
+    }
+
+    pub fn export(&mut self, telemetry: &RoverMessage) -> Result<()> {
+        let point = line_protocol(telemetry, self.rover_address)?;
+        self.points.try_send(point).map_err(|e| format!("InfluxDB export failed: {}", e))?;
+        Ok(())
+    }
+}
+
+// one point, e.g.
+// rover_telemetry,rover=1 lat=37.42,long=-122.08,...,free_memory=1024i 1623672225000000000
+fn line_protocol(telemetry: &RoverMessage, rover_address: Option<u8>) -> Result<String> {
+    match telemetry {
+        RoverMessage::TelemetryMessage { timestamp, location, signal_strength, free_memory, battery_millivolts, .. } => {
+            let tags = rover_address.map_or(String::new(), |address| format!(",rover={}", address));
+            let time = timestamp.to_datetime().ok_or_else(|| format!("impossible timestamp {:?}", timestamp))?;
+            Ok(format!("{}{} lat={},long={},alt={},speed={},sats={}i,heading={}i,rssi={}i,free_memory={}i,battery_millivolts={}i {}",
+                       MEASUREMENT, tags, location.gps_lat, location.gps_long, location.gps_alt, location.gps_speed,
+                       location.gps_sats, location.gps_hdg, signal_strength, free_memory, battery_millivolts,
+                       time.timestamp() * 1_000_000_000))
+        },
+        _ => Err(format!("only telemetry can be exported, not {}", telemetry).into())
+    }
+}
+
+// the writer thread: gather up to batch_size points, or whatever turns up in
+// BATCH_WAIT, and write them all at once
+fn write_points(queue: Receiver<String>, url: &str, token: Option<&str>, batch_size: usize) {
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_millis(WRITE_TIMEOUT)).build();
+    while let Ok(point) = queue.recv() {
+        let mut batch = vec![point];
+        let deadline = Instant::now() + Duration::from_millis(BATCH_WAIT);
+        while batch.len() < batch_size {
+            match queue.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(point) => batch.push(point),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break
+            }
+        }
+        write_batch(&agent, url, token, &batch);
+    }
+}
+
+fn write_batch(agent: &ureq::Agent, url: &str, token: Option<&str>, batch: &[String]) {
+    let body = batch.join("\n");
+    for attempt in 1..=WRITE_ATTEMPTS {
+        if attempt > 1 {
+            thread::sleep(Duration::from_millis(RETRY_DELAY * (attempt - 1)));
+        }
+        let mut request = agent.post(url);
+        if let Some(token) = token {
+            request = request.set("Authorization", &format!("Token {}", token));
+        }
+        match request.send_string(&body) {
+            Ok(_) => return,
+            Err(e) => warn!("InfluxDB write failed (attempt {} of {}): {}", attempt, WRITE_ATTEMPTS, e)
+        }
+    }
+    warn!("Dropping {} telemetry points that couldn't be written to InfluxDB", batch.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry_as_line_protocol() {
+        let telemetry = RoverMessage::TelemetryMessage {
+            timestamp: RoverTimestamp { year: 21, month: 6, day: 14, hour: 12, minute: 3, second: 45 },
+            location: RoverLocData { gps_lat: 37.5, gps_long: -122.25, gps_alt: 30.0, gps_speed: 1.5, gps_sats: 8, gps_hdg: 270 },
+            signal_strength: -72,
+            free_memory: 1024,
+            battery_millivolts: 7400,
+            status: "OK".into()
+        };
+        assert_eq!(line_protocol(&telemetry, Some(1)).unwrap(),
+                   "rover_telemetry,rover=1 lat=37.5,long=-122.25,alt=30,speed=1.5,sats=8i,heading=270i,rssi=-72i,\
+                    free_memory=1024i,battery_millivolts=7400i 1623672225000000000");
+        assert!(line_protocol(&telemetry, None).unwrap().starts_with("rover_telemetry lat="));
+        assert!(line_protocol(&RoverMessage::ping(), None).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+// loads the AES key and sync words from a file, so they stay out of source
+// control and can be swapped per deployment without a rebuild.
+//
+// the file is a list of `name = hex` lines; blank lines and lines starting
+// with # are ignored:
+//
+//   # ground control radio keys
+//   key = 00112233445566778899aabbccddeeff
+//   sync = 2dd4
+//
+// the key can be left out of a station that doesn't use encryption.
+
+use crate::config;
+use crate::errors::*;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+// the RFM69 does AES-128 only, and matches between 1 and 8 sync word bytes
+const AES_KEY_SIZE: usize = 16;
+const MIN_SYNC_WORDS: usize = 1;
+const MAX_SYNC_WORDS: usize = 8;
+
+pub struct RadioKeys {
+    pub aes_key: Option<Vec<u8>>,
+    pub sync_words: Vec<u8>,
+}
+
+// ~/.ground_control/key
+pub fn default_key_path() -> PathBuf {
+    config::ground_control_dir().join("key")
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<RadioKeys> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .chain_err(|| ErrorKind::RadioError(format!("reading key file {}", path.display())))?;
+    let mut aes_key = None;
+    let mut sync_words = None;
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue; }
+        let malformed = || ErrorKind::RadioError(format!("{} line {}: malformed", path.display(), i + 1));
+        let mut parts = line.splitn(2, '=');
+        let name = parts.next().unwrap_or_default().trim();
+        let value = parts.next().ok_or_else(malformed)?.trim();
+        match name {
+            "key" => aes_key = Some(parse_hex(value).ok_or_else(malformed)?),
+            "sync" => sync_words = Some(parse_hex(value).ok_or_else(malformed)?),
+            _ => return Err(malformed().into())
+        }
+    }
+    let sync_words = sync_words.ok_or_else(|| ErrorKind::RadioError(format!("{}: no sync", path.display())))?;
+    if sync_words.len() < MIN_SYNC_WORDS || sync_words.len() > MAX_SYNC_WORDS {
+        return Err(ErrorKind::RadioError(format!("{}: sync must be {}-{} bytes, got {}", path.display(),
+                                                 MIN_SYNC_WORDS, MAX_SYNC_WORDS, sync_words.len())).into());
+    }
+    // rfm69 would only say the key is the wrong size once it's being set up
+    if let Some(aes_key) = &aes_key {
+        if aes_key.len() != AES_KEY_SIZE {
+            return Err(ErrorKind::RadioError(format!("{}: AES key must be {} bytes, got {}", path.display(),
+                                                     AES_KEY_SIZE, aes_key.len())).into());
+        }
+    }
+    Ok(RadioKeys { aes_key, sync_words })
+}
+
+// "2dd4" -> [0x2d, 0xd4]
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.is_ascii() { return None; }
+    (0..s.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_must_be_16_bytes() {
+        let path = std::env::temp_dir().join(format!("ground_control_key_{}", std::process::id()));
+        fs::write(&path, "key = 00112233445566778899aabb\nsync = 2dd4\n").unwrap();
+        let short = load(&path);
+        fs::write(&path, "# test key\nkey = 00112233445566778899aabbccddeeff\nsync = 2dd4\n").unwrap();
+        let keys = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        match short {
+            Err(Error(ErrorKind::RadioError(message), _)) => assert!(message.ends_with("AES key must be 16 bytes, got 12")),
+            other => panic!("expected a RadioError, got {:?}", other.map(|_| ()))
+        }
+        assert_eq!(keys.aes_key.map(|key| key.len()), Some(16));
+        assert_eq!(keys.sync_words, vec![0x2d, 0xd4]);
+    }
+
+    #[test]
+    fn key_can_be_left_out() {
+        let path = std::env::temp_dir().join(format!("ground_control_sync_only_{}", std::process::id()));
+        fs::write(&path, "sync = 2dd4\n").unwrap();
+        let keys = load(&path);
+        fs::remove_file(&path).unwrap();
+        let keys = keys.unwrap();
+        assert!(keys.aes_key.is_none());
+        assert_eq!(keys.sync_words, vec![0x2d, 0xd4]);
+    }
+}
@@ -0,0 +1,239 @@
+// state kept about the radio link from one message to the next: a rolling
+// signal strength history, so an operator can tell whether the link is
+// getting better or worse as the rover moves, the latest ping round trip time
+// and frequency offset, how many packets failed their CRC (a rising rate is
+// the first sign of a link getting marginal), and the sequence numbers used to
+// spot retransmissions. there's also a count of every kind of message received.
+// received frames can also be captured to a file from here, and the signal
+// strength logged against the rover's position.
+
+use crate::capture::FrameCapture;
+use crate::signal_logger::SignalLogger;
+use std::collections::{ BTreeMap, HashMap, VecDeque };
+use std::time::Duration;
+
+const RSSI_HISTORY: usize = 32;  // how many of the most recent readings to keep
+
+pub struct LinkStats {
+    rssi: VecDeque<f32>,
+    radio_temperature: Option<i8>,  // degrees C, from the RFM69's own sensor
+    rtt: Option<Duration>,          // Ping to Pong
+    rtt_total: Duration,            // every round trip so far, for the mean
+    rtt_count: u32,
+    afc_offset: Option<i32>,        // Hz, the frequency correction for the last packet
+    packets: u64,                   // every packet that passed its CRC
+    crc_failures: u64,
+    pub sequences: Sequences,
+    pub messages: MessageStats,
+    pub capture: Option<FrameCapture>,  // every frame received, if --capture was given
+    pub signal_log: Option<SignalLogger>,  // RSSI and position for every telemetry packet, if there's a signal_path
+}
+
+impl LinkStats {
+    pub fn new() -> LinkStats {
+        LinkStats { rssi: VecDeque::with_capacity(RSSI_HISTORY),
+                    radio_temperature: None,
+                    rtt: None,
+                    rtt_total: Duration::ZERO,
+                    rtt_count: 0,
+                    afc_offset: None,
+                    packets: 0,
+                    crc_failures: 0,
+                    sequences: Default::default(),
+                    messages: Default::default(),
+                    capture: None,
+                    signal_log: None }
+    }
+
+    // add the RSSI of a just-received packet, dropping the oldest reading
+    // once the history is full. every good packet has one, so this counts
+    // them too.
+    pub fn record_rssi(&mut self, rssi: f32) {
+        self.packets += 1;
+        if self.rssi.len() == RSSI_HISTORY {
+            self.rssi.pop_front();
+        }
+        self.rssi.push_back(rssi);
+    }
+
+    pub fn record_radio_temperature(&mut self, temperature: i8) {
+        self.radio_temperature = Some(temperature);
+    }
+
+    // the most recent transceiver temperature reading, if one has been taken
+    pub fn radio_temperature(&self) -> Option<i8> {
+        self.radio_temperature
+    }
+
+    pub fn record_rtt(&mut self, rtt: Duration) {
+        self.rtt = Some(rtt);
+        self.rtt_total += rtt;
+        self.rtt_count += 1;
+    }
+
+    // round trip time of the most recent answered ping
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    // over every answered ping, not just the recent ones
+    pub fn mean_rtt(&self) -> Option<Duration> {
+        match self.rtt_count {
+            0 => None,
+            count => Some(self.rtt_total / count)
+        }
+    }
+
+    pub fn record_afc_offset(&mut self, offset: i32) {
+        self.afc_offset = Some(offset);
+    }
+
+    pub fn afc_offset(&self) -> Option<i32> {
+        self.afc_offset
+    }
+
+    pub fn record_crc_failure(&mut self) {
+        self.crc_failures += 1;
+    }
+
+    pub fn crc_failures(&self) -> u64 {
+        self.crc_failures
+    }
+
+    // the fraction of everything received that failed its CRC, None until
+    // something has been
+    pub fn crc_failure_rate(&self) -> Option<f32> {
+        match self.packets + self.crc_failures {
+            0 => None,
+            total => Some(self.crc_failures as f32 / total as f32)
+        }
+    }
+
+    // the min/max/mean are None until at least one packet has arrived
+    pub fn min_rssi(&self) -> Option<f32> {
+        self.rssi.iter().cloned().fold(None, |min, r| Some(min.map_or(r, |m: f32| m.min(r))))
+    }
+
+    pub fn max_rssi(&self) -> Option<f32> {
+        self.rssi.iter().cloned().fold(None, |max, r| Some(max.map_or(r, |m: f32| m.max(r))))
+    }
+
+    pub fn mean_rssi(&self) -> Option<f32> {
+        if self.rssi.is_empty() {
+            None
+        } else {
+            Some(self.rssi.iter().sum::<f32>() / self.rssi.len() as f32)
+        }
+    }
+}
+
+// sequence numbers, kept separately for each message type: the next one to
+// send, and the last one received
+#[derive(Default)]
+pub struct Sequences {
+    sent: HashMap<u8, u8>,
+    received: HashMap<u8, u8>,
+}
+
+impl Sequences {
+    // sequence number for a new message of the given type
+    pub fn next(&mut self, message_id: u8) -> u8 {
+        let next = self.sent.entry(message_id).or_insert(0);
+        let sequence = *next;
+        *next = next.wrapping_add(1);
+        sequence
+    }
+
+    // note a received message, returning true if it has the same sequence
+    // number as the last one of its type (i.e. it's a retransmission)
+    pub fn is_duplicate(&mut self, message_id: u8, sequence: u8) -> bool {
+        self.received.insert(message_id, sequence) == Some(sequence)
+    }
+}
+
+// how many of each kind of message have been received, by message id, and
+// how many packets couldn't be made sense of: ones that failed the checksum
+// or wouldn't deserialize, and ones with a message id we don't know
+#[derive(Default)]
+pub struct MessageStats {
+    received: BTreeMap<u8, u64>,
+    malformed: u64,
+    unknown: u64,
+}
+
+impl MessageStats {
+    pub fn record(&mut self, message_id: u8) {
+        *self.received.entry(message_id).or_insert(0) += 1;
+    }
+
+    pub fn record_malformed(&mut self) {
+        self.malformed += 1;
+    }
+
+    pub fn record_unknown(&mut self) {
+        self.unknown += 1;
+    }
+
+    // (message id, count) in message id order, for the ones that have turned up
+    pub fn received(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        self.received.iter().map(|(id, count)| (*id, *count))
+    }
+
+    pub fn malformed(&self) -> u64 {
+        self.malformed
+    }
+
+    pub fn unknown(&self) -> u64 {
+        self.unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_history_has_no_stats() {
+        let stats = LinkStats::new();
+        assert_eq!(stats.min_rssi(), None);
+        assert_eq!(stats.max_rssi(), None);
+        assert_eq!(stats.mean_rssi(), None);
+    }
+
+    #[test]
+    fn only_recent_readings_are_kept() {
+        let mut stats = LinkStats::new();
+        // the first reading should have aged out by the end
+        stats.record_rssi(-20.0);
+        for _ in 0..RSSI_HISTORY {
+            stats.record_rssi(-80.0);
+        }
+        stats.record_rssi(-60.0);
+        assert_eq!(stats.min_rssi(), Some(-80.0));
+        assert_eq!(stats.max_rssi(), Some(-60.0));
+        assert_eq!(stats.mean_rssi(), Some((-80.0 * 31.0 - 60.0) / 32.0));
+    }
+
+    #[test]
+    fn messages_are_counted_by_id() {
+        let mut messages = MessageStats::default();
+        for id in [6, 0, 0, 7] {
+            messages.record(id);
+        }
+        messages.record_malformed();
+        assert_eq!(messages.received().collect::<Vec<_>>(), vec![(0, 2), (6, 1), (7, 1)]);
+        assert_eq!((messages.malformed(), messages.unknown()), (1, 0));
+    }
+
+    #[test]
+    fn crc_failure_rate() {
+        let mut stats = LinkStats::new();
+        assert_eq!(stats.crc_failure_rate(), None);
+        stats.record_crc_failure();
+        for _ in 0..3 {
+            stats.record_rssi(-70.0);
+        }
+        assert_eq!(stats.crc_failures(), 1);
+        assert_eq!(stats.crc_failure_rate(), Some(0.25));
+    }
+}
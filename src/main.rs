@@ -15,167 +15,1175 @@
 #[macro_use]
 extern crate error_chain;
 
+use clap::Parser;
+use error_chain::ChainedError;
 use errors::*;
+use log::{ debug, error, info, log_enabled, trace, warn, Level };
 use rfm69:: {
     Rfm69,
-    registers:: { DataMode, DccCutoff, FifoMode, InterPacketRxDelay, Modulation, ModulationShaping, ModulationType,
+    registers:: { DataMode, Mode, DccCutoff, FifoMode, InterPacketRxDelay, Modulation, ModulationShaping, ModulationType,
                   PacketConfig, PacketDc, PacketFiltering, PacketFormat, Registers, RxBw, RxBwFsk }
 };
 use rppal:: {
     gpio::{Gpio, OutputPin},
     i2c::I2c,
-    spi::{Bus, SlaveSelect, Spi}
+    spi::Spi
 };
 use ssd1306:: {
-    mode::TerminalMode,
+    mode::{ GraphicsMode, TerminalMode },
     prelude::*,
     Builder,
     I2CDIBuilder
 };
 use std:: {
     fmt::Write,
+    io::{ self, BufRead, Write as _ },
+    path::Path,
+    sync::{ Arc, atomic::{ AtomicBool, Ordering } },
     thread,
     time
 };
+use crate::alerts::Alerter;
+use crate::cli::{ Args, Command };
+use crate::capture::FrameCapture;
+use crate::clock_drift::ClockWatch;
+use crate::command_queue::CommandQueue;
+use crate::command_session::{ CommandSession, SessionState };
+use crate::config::Config;
+use crate::dashboard::Dashboard;
+use crate::dead_reckoning::{ DeadReckoning, Estimate };
+use crate::display_graphics::Gauges;
+use crate::display_template::DisplayMode;
+use crate::gpx::GpxWriter;
+use crate::http_server::LatestTelemetry;
+use crate::influx::InfluxExporter;
+use crate::link_stats::LinkStats;
+use crate::memory_watch::{ MemoryState, MemoryWatch };
 use crate::messages::*;
-use crate::encryption_key::{ ENCRYPTION_KEY, SYNC_WORDS };
+use crate::mission_report::MissionTracker;
+use crate::mqtt::MqttPublisher;
+use crate::radio::{ read_registers, register_table, MIN_HIGH_POWER_LEVEL, RadioConfig, RadioLink, Rfm69Error, Rfm69Radio };
+use crate::scan::scan_for_rover;
+use crate::serial_radio::SerialRadio;
+use crate::signal_logger::SignalLogger;
+use crate::synthetic::TelemetryGenerator;
+use crate::telemetry_logger::TelemetryLogger;
+use crate::throttle::Throttle;
+use crate::watchdog::Watchdog;
+use crate::websocket::TelemetryFeed;
 
+mod alerts;
+mod capture;
+mod clock_drift;
+mod cli;
+mod command_queue;
+mod command_session;
+mod config;
+mod dashboard;
+mod dead_reckoning;
+mod display_graphics;
+mod display_template;
 mod errors;
+mod gpx;
+mod http_server;
+mod influx;
+mod keyfile;
+mod link_stats;
+mod memory_watch;
 mod messages;
-mod encryption_key;
-
-// set up the OLED display on the RFM69 bonnet
-fn setup_display() -> Result<TerminalMode<I2CInterface<I2c>, DisplaySize128x32>> {
-    // initialize the display on the RFM69 bonnet
-    let i2c = I2c::new()?;
-    let interface = I2CDIBuilder::new().init(i2c);
-    let mut disp: TerminalMode<_, _> = Builder::new()
+mod mission_report;
+mod mqtt;
+mod radio;
+mod scan;
+mod serial_radio;
+mod signal_logger;
+mod synthetic;
+mod telemetry_logger;
+mod throttle;
+mod watchdog;
+mod websocket;
+
+// the 128x32 OLED on the bonnet, used as a 16x4 character terminal or drawn
+// on, as the display config says
+type TextDisplay = TerminalMode<I2CInterface<I2c>, DisplaySize128x32>;
+type GraphicsDisplay = GraphicsMode<I2CInterface<I2c>, DisplaySize128x32>;
+const DISPLAY_COLUMNS: usize = 16;
+
+enum OledDisplay {
+    Text(TextDisplay),
+    Graphics(GraphicsDisplay, Gauges),  // with what's drawn on it, since every change redraws the lot
+}
+const LINK_LOST_SECS: u64 = 30;  // seconds without a packet before the link is reported lost
+const RADIO_ERROR_LIMIT: u32 = 3;  // consecutive radio errors before the radio gets reset
+const RECEIVE_TIMEOUT_MS: u64 = 10000;  // how long the receive loop listens for the rover each time round
+const TEMPERATURE_INTERVAL_SECS: u64 = 60;  // seconds between radio temperature readings
+
+// wrap an rfm69 error up as a RadioError saying what we were doing at the time
+fn radio_err<Ecs, Espi>(context: &'static str) -> impl FnOnce(rfm69::Error<Ecs, Espi>) -> Error
+        where Ecs: std::fmt::Debug, Espi: std::fmt::Debug {
+    move |e| Error::with_chain(Rfm69Error::from(e), ErrorKind::RadioError(context.into()))
+}
+
+// ssd1306 doesn't implement std::error::Error on its error types, so they
+// can't be chained; fold them into a DisplayError instead
+fn display_err<E: std::fmt::Debug>(context: &'static str) -> impl FnOnce(E) -> Error {
+    move |e| ErrorKind::DisplayError(format!("{}: {:?}", context, e)).into()
+}
+
+// set up the OLED display on the RFM69 bonnet. graphics mode falls back to
+// text if it can't be set up, since some display is better than none.
+fn setup_display(mode: DisplayMode) -> Result<OledDisplay> {
+    if mode == DisplayMode::Graphics {
+        match setup_graphics_display() {
+            Ok(disp) => return Ok(OledDisplay::Graphics(disp, Gauges::default())),
+            Err(e) => warn!("Falling back to text on the display: {}", e.display_chain())
+        }
+    }
+    setup_text_display().map(OledDisplay::Text)
+}
+
+fn display_interface() -> Result<I2CInterface<I2c>> {
+    let i2c = I2c::new().chain_err(|| ErrorKind::DisplayError("opening I2C bus".into()))?;
+    Ok(I2CDIBuilder::new().init(i2c))
+}
+
+fn setup_text_display() -> Result<TextDisplay> {
+    let mut disp: TextDisplay = Builder::new()
         .size(DisplaySize128x32)
-        .connect(interface)
+        .connect(display_interface()?)
         .into();
-    match disp.init() {
-        Err(e) => return Err(format!("Error while initializing display: {:?}", e).into()),
-        _ => {}
+    disp.init().map_err(display_err("initializing display"))?;
+    disp.clear().map_err(display_err("clearing display"))?;
+    Ok(disp)
+}
+
+fn setup_graphics_display() -> Result<GraphicsDisplay> {
+    let mut disp: GraphicsDisplay = Builder::new()
+        .size(DisplaySize128x32)
+        .connect(display_interface()?)
+        .into();
+    disp.init().map_err(display_err("initializing display in graphics mode"))?;
+    disp.clear();
+    disp.flush().map_err(display_err("clearing display"))?;
+    Ok(disp)
+}
+
+// put up a message of a few lines, on a clear screen
+fn display_message(disp: &mut OledDisplay, message: &str) -> Result<()> {
+    match disp {
+        OledDisplay::Text(disp) => {
+            disp.clear().map_err(display_err("clearing display"))?;
+            disp.write_str(message).map_err(display_err("writing to display"))?;
+        },
+        OledDisplay::Graphics(disp, _) => {
+            disp.clear();
+            display_graphics::draw_message(disp, message).map_err(display_err("drawing on display"))?;
+            disp.flush().map_err(display_err("writing to display"))?;
+        }
+    }
+    Ok(())
+}
+
+// redraw the graphics display from scratch
+fn draw_gauges(disp: &mut GraphicsDisplay, gauges: &Gauges) -> Result<()> {
+    disp.clear();
+    gauges.draw(disp).map_err(display_err("drawing on display"))?;
+    disp.flush().map_err(display_err("writing to display"))
+}
+
+// make a change to the display. over a long run the I2C bus to it can
+// glitch, and rather than leave the screen dead after one error, set the
+// display up again and have another go. if that fails too, carry on without
+// it; losing the screen shouldn't stop the receive loop.
+fn write_display<F>(disp: &mut Option<OledDisplay>, mut write: F) where F: FnMut(&mut OledDisplay) -> Result<()> {
+    let old = match disp.take() {
+        Some(mut old) => match write(&mut old) {
+            Ok(()) => {
+                *disp = Some(old);
+                return;
+            },
+            Err(e) => {
+                warn!("{}; setting the display up again", e.display_chain());
+                old
+            }
+        },
+        None => return
+    };
+    match reset_display(old).and_then(|mut fresh| write(&mut fresh).map(|_| fresh)) {
+        Ok(fresh) => {
+            info!("Display set up again");
+            *disp = Some(fresh);
+        },
+        Err(e) => warn!("Carrying on without the display: {}", e.display_chain())
     }
-    match disp.clear() {
-        Err(e) => return Err(format!("Error while clearing display: {:?}", e).into()),
-        _ => {}
+}
+
+// set the display up again from scratch, the same way it was before, with
+// the gauges redrawn if it had them
+fn reset_display(disp: OledDisplay) -> Result<OledDisplay> {
+    match disp {
+        OledDisplay::Text(_) => setup_text_display().map(OledDisplay::Text),
+        OledDisplay::Graphics(_, gauges) => {
+            let mut disp = setup_graphics_display()?;
+            draw_gauges(&mut disp, &gauges)?;
+            Ok(OledDisplay::Graphics(disp, gauges))
+        }
+    }
+}
+
+// frequency bands the RFM69 synthesizer can actually tune to, in Hz (see the
+// RFM69HCW datasheet). anything outside these gets silently clamped by the chip.
+const FREQUENCY_BANDS: [(f64, f64); 3] = [(290_000_000.0, 340_000_000.0),
+                                          (424_000_000.0, 510_000_000.0),
+                                          (862_000_000.0, 1_020_000_000.0)];
+
+// PaLevel register bits. the HCW only brings out the PA_BOOST pin, which is
+// driven by PA1 and PA2 - PA0 isn't connected, so it must stay off.
+const PA1_ON: u8 = 0b010_00000;
+const PA2_ON: u8 = 0b001_00000;
+const MAX_POWER_LEVEL: u8 = 0b000_11111;
+
+// FSK limits from the RFM69HCW datasheet
+const FSTEP_HZ: f32 = 32_000_000.0 / 524_288.0;  // synthesizer step, FXOSC / 2^19
+const MIN_BIT_RATE: f32 = 1200.0;
+const MAX_BIT_RATE: f32 = 300_000.0;
+const MAX_FDEV_RAW: u16 = 0x3fff;  // Fdev is 14 bits
+const RX_BW_HZ: f32 = 25_000.0;  // matches the RxBwFsk::Khz25dot0 set in setup_radio
+
+// work out the Fdev register value for the configured deviation, and make
+// sure the signal it produces will fit through the receiver's filter
+fn fdev_register(config: &RadioConfig) -> Result<u16> {
+    if config.bit_rate < MIN_BIT_RATE || config.bit_rate > MAX_BIT_RATE {
+        return Err(ErrorKind::RadioError(format!("bit rate must be {}-{} bps, got {}",
+                                                 MIN_BIT_RATE, MAX_BIT_RATE, config.bit_rate)).into());
+    }
+    let raw = match config.fdev_raw {
+        Some(raw) => raw,
+        None => (config.fdev_hz / FSTEP_HZ).round() as u16
+    };
+    if raw > MAX_FDEV_RAW {
+        return Err(ErrorKind::RadioError(format!("Fdev register value 0x{:04x} is more than 14 bits", raw)).into());
+    }
+    // the occupied bandwidth is roughly fdev + bit rate / 2 either side of the carrier
+    let fdev = f32::from(raw) * FSTEP_HZ;
+    if fdev + config.bit_rate / 2.0 > RX_BW_HZ {
+        return Err(ErrorKind::RadioError(format!("deviation of {:.0} Hz at {} bps won't fit in the {} Hz receiver bandwidth",
+                                                 fdev, config.bit_rate, RX_BW_HZ)).into());
+    }
+    Ok(raw)
+}
+
+// make sure the requested carrier frequency is one the RFM69 supports
+fn check_frequency(frequency_hz: f64) -> Result<()> {
+    if FREQUENCY_BANDS.iter().any(|(low, high)| frequency_hz >= *low && frequency_hz <= *high) {
+        Ok(())
+    } else {
+        Err(ErrorKind::RadioError(format!("carrier frequency {} Hz is outside the RFM69's supported bands", frequency_hz)).into())
+    }
+}
+
+// compose the PaLevel register value for the given output power level. the
+// high power settings only work from +5 dBm up (see Rfm69Radio::send for
+// the rest of them).
+fn pa_level(power_level: u8, high_power: bool) -> Result<u8> {
+    if power_level > MAX_POWER_LEVEL {
+        return Err(ErrorKind::RadioError(format!("power level must be 0-{}, got {}", MAX_POWER_LEVEL, power_level)).into());
+    }
+    if high_power && power_level < MIN_HIGH_POWER_LEVEL {
+        return Err(ErrorKind::RadioError(format!("power level must be {}-{} in high power mode, got {}",
+                                                 MIN_HIGH_POWER_LEVEL, MAX_POWER_LEVEL, power_level)).into());
+    }
+    Ok(PA1_ON | PA2_ON | power_level)
+}
+
+// the InterPacketRxDelay for a delay in bit times, which the RFM69 only
+// offers in powers of two
+fn interpacket_rx_delay(bits: u16) -> Result<InterPacketRxDelay> {
+    const DELAYS: [InterPacketRxDelay; 12] = [
+        InterPacketRxDelay::Delay1Bit, InterPacketRxDelay::Delay2Bits, InterPacketRxDelay::Delay4Bits,
+        InterPacketRxDelay::Delay8Bits, InterPacketRxDelay::Delay16Bits, InterPacketRxDelay::Delay32Bits,
+        InterPacketRxDelay::Delay64Bits, InterPacketRxDelay::Delay128Bits, InterPacketRxDelay::Delay256Bits,
+        InterPacketRxDelay::Delay512Bits, InterPacketRxDelay::Delay1024Bits, InterPacketRxDelay::Delay2048Bits];
+    match DELAYS.get(bits.trailing_zeros() as usize) {
+        Some(delay) if bits.is_power_of_two() => Ok(*delay),
+        _ => Err(ErrorKind::RadioError(format!("interpacket RX delay must be a power of two from 1 to 2048 bits, got {}",
+                                               bits)).into())
     }
-    Ok(disp)
 }
 
 // set up the RFM69
-fn setup_radio() -> Result<Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>> {
+fn setup_radio(config: &RadioConfig) -> Result<Rfm69Radio> {
+    // check the settings before touching the hardware
+    check_frequency(config.frequency_hz)?;
+    pa_level(config.power_level, config.high_power)?;
+    fdev_register(config)?;
+    interpacket_rx_delay(config.interpacket_rx_delay_bits)?;
     // initialize the RFM69 radio
     // see https://github.com/almusil/rfm69/blob/master/examples/receive.rs
-    let gpio = Gpio::new()?;
+    let gpio = Gpio::new().chain_err(|| ErrorKind::RadioError("opening GPIO".into()))?;
     // configure CS pin
-    let mut cs = gpio.get(7)?.into_output();
+    let mut cs = gpio.get(config.pins.cs_pin).chain_err(|| ErrorKind::RadioError("configuring CS pin".into()))?.into_output();
     cs.set_high();
     cs.set_reset_on_drop(false);
     // configure reset pin
-    let mut reset = gpio.get(25)?.into_output();
+    let mut reset = gpio.get(config.pins.reset_pin).chain_err(|| ErrorKind::RadioError("configuring reset pin".into()))?.into_output();
     reset.set_low();
     reset.set_reset_on_drop(false);
-    // reset the RFM69 the same way the CircuitPython code does
+    // DIO0 tells us when a packet is waiting
+    let dio0 = gpio.get(config.pins.dio0_pin).chain_err(|| ErrorKind::RadioError("configuring DIO0 pin".into()))?.into_input();
+    // configure SPI 8 bits, Mode 0
+    let spi = Spi::new(config.pins.spi_bus, config.pins.slave_select, 2_000_000, rppal::spi::Mode::Mode0)
+        .chain_err(|| ErrorKind::RadioError("opening SPI bus".into()))?;
+    let mut radio = Rfm69Radio::new(Rfm69::new(spi, cs, linux_embedded_hal::Delay), reset, dio0, config.high_power)?;
+    restart_radio(&mut radio, config)?;
+    info!("Carrier frequency: {} MHz", get_frequency(&mut radio.rfm)?);
+    Ok(radio)
+}
+
+// reset the RFM69 the same way the CircuitPython code does
+const RESET_PULSE_MS: u64 = 100;
+const RESET_SETTLE_MS: u64 = 1000;
+
+fn reset_radio(reset: &mut OutputPin) {
     reset.set_high();
-    thread::sleep(time::Duration::from_millis(100));
+    thread::sleep(time::Duration::from_millis(RESET_PULSE_MS));
     reset.set_low();
-    thread::sleep(time::Duration::from_millis(1000));
-    // configure SPI 8 bits, Mode 0
-    let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 2_000_000, rppal::spi::Mode::Mode0)?;
-    let mut rfm = Rfm69::new(spi, cs, linux_embedded_hal::Delay);
+    thread::sleep(time::Duration::from_millis(RESET_SETTLE_MS));
+}
+
+// reset and reconfigure the radio. a radio that doesn't answer with the right
+// version after its reset gets one more go before we give up on it.
+fn restart_radio(radio: &mut Rfm69Radio, config: &RadioConfig) -> Result<()> {
+    reset_radio(&mut radio.reset);
+    if let Err(e) = check_version(&mut radio.rfm) {
+        warn!("{}, resetting again", e);
+        reset_radio(&mut radio.reset);
+        check_version(&mut radio.rfm)?;
+    }
+    configure_radio(&mut radio.rfm, config)
+}
+
+// the radio the station is running on: the one on the bonnet, or one behind
+// a serial gateway. only the bonnet's can report its temperature and AFC offset.
+enum StationRadio {
+    Bonnet(Rfm69Radio),
+    Serial(SerialRadio),
+}
+
+impl StationRadio {
+    fn open(config: &Config) -> Result<StationRadio> {
+        match &config.serial {
+            Some(serial) => {
+                info!("Using the serial gateway on {} at {} baud", serial.port, serial.baud);
+                Ok(StationRadio::Serial(SerialRadio::open(serial)?))
+            },
+            None => Ok(StationRadio::Bonnet(setup_radio(&config.radio)?))
+        }
+    }
+
+    fn restart(&mut self, config: &RadioConfig) -> Result<()> {
+        match self {
+            StationRadio::Bonnet(radio) => restart_radio(radio, config),
+            StationRadio::Serial(radio) => radio.reopen()
+        }
+    }
+}
+
+impl RadioLink for StationRadio {
+    fn send(&mut self, buf: &[u8]) -> Result<()> {
+        match self {
+            StationRadio::Bonnet(radio) => radio.send(buf),
+            StationRadio::Serial(radio) => radio.send(buf)
+        }
+    }
+
+    fn recv(&mut self, buf: &mut [u8], wait: time::Duration) -> Result<usize> {
+        match self {
+            StationRadio::Bonnet(radio) => radio.recv(buf, wait),
+            StationRadio::Serial(radio) => radio.recv(buf, wait)
+        }
+    }
+
+    fn rssi(&self) -> f32 {
+        match self {
+            StationRadio::Bonnet(radio) => radio.rssi(),
+            StationRadio::Serial(radio) => radio.rssi()
+        }
+    }
+
+    fn channel_rssi(&mut self) -> Result<f32> {
+        match self {
+            StationRadio::Bonnet(radio) => radio.channel_rssi(),
+            StationRadio::Serial(radio) => radio.channel_rssi()
+        }
+    }
+}
+
+// step through the frequencies in config.scan until the rover turns up (see scan.rs)
+fn scan(radio: &mut StationRadio, config: &Config, stats: &mut LinkStats, running: &AtomicBool) -> Result<Option<f64>> {
+    match radio {
+        StationRadio::Bonnet(radio) => scan_for_rover(radio, &config.radio, &config.scan, stats, running, |radio, frequency_hz| {
+            check_frequency(frequency_hz)?;
+            radio.rfm.frequency(frequency_hz as f32).map_err(radio_err("setting frequency"))
+        }),
+        StationRadio::Serial(_) => {
+            warn!("The serial gateway can't be scanned; it stays on its own frequency");
+            Ok(None)
+        }
+    }
+}
+
+// check for good connection by reading back version register
+// see https://github.com/adafruit/Adafruit_CircuitPython_RFM69/blob/ad33b2948a13df1c0e036605ef1fb5e6484ea97e/adafruit_rfm69.py#L263
+fn check_version(rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>) -> Result<()> {
+    let version = rfm.read(Registers::Version).map_err(radio_err("connecting to RFM69"))?;
+    info!("RFM69 version: 0x{:02x}", version);
+    if version != 0x24 {
+        return Err(ErrorKind::RadioError(format!("expected RFM69 version 0x24, got 0x{:02x}", version)).into());
+    }
+    Ok(())
+}
+
+// write our settings to a freshly reset RFM69
+fn configure_radio(rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>, config: &RadioConfig) -> Result<()> {
+    let pa_level = pa_level(config.power_level, config.high_power)?;
+    let fdev = fdev_register(config)?;
+    let interpacket_rx_delay = interpacket_rx_delay(config.interpacket_rx_delay_bits)?;
+    let keys = keyfile::load(&config.key_path)?;
+    let aes_key = match (config.use_encryption, &keys.aes_key) {
+        (true, None) => return Err(ErrorKind::RadioError(format!("{}: no key, and use_encryption is on",
+                                                                 config.key_path.display())).into()),
+        (true, Some(key)) => Some(key),
+        (false, _) => None
+    };
     rfm.modulation(Modulation { data_mode: DataMode::Packet,
                                 modulation_type: ModulationType::Fsk,
                                 shaping: ModulationShaping::Shaping00 })  // no shaping
-                                .expect("Radio error setting modulation");
-    rfm.bit_rate(9600.0).expect("Radio error setting bit rate");
-    rfm.frequency(915_000_000.0).expect("Radio error setting frequency");
-    // don't know if it matters, but the value computed by fdev() is off by 1 from what the sender has.
-    // therefore, set the exact value.
-    // instead of: rfm.fdev(19200.0).expect("Radio error setting fdev");
-    rfm.write(Registers::FdevMsb, 0x01).expect("Radio error setting FdevMsb");
-    rfm.write(Registers::FdevLsb, 0x38).expect("Radio error setting FdevLsb");
+                                .map_err(radio_err("setting modulation"))?;
+    rfm.bit_rate(config.bit_rate).map_err(radio_err("setting bit rate"))?;
+    rfm.frequency(config.frequency_hz as f32).map_err(radio_err("setting frequency"))?;
+    // write the registers directly rather than using fdev(), so that an exact
+    // value from the config (see RadioConfig::fdev_raw) is honored
+    rfm.write(Registers::FdevMsb, (fdev >> 8) as u8).map_err(radio_err("setting FdevMsb"))?;
+    rfm.write(Registers::FdevLsb, fdev as u8).map_err(radio_err("setting FdevLsb"))?;
     // preamble - default 4 octets per RadioHead
-    rfm.preamble(4).expect("Radio error setting preamble");
-    // sync - default 2 bytes (0x2d, 0xd4) per RadioHead
-    // TODO: choose other values to replace these defaults
-    rfm.sync(&SYNC_WORDS).expect("Radio error setting sync words"); // defined in encryption_key.rs
+    rfm.preamble(4).map_err(radio_err("setting preamble"))?;
+    // sync - RadioHead defaults to 2 bytes (0x2d, 0xd4); ours come from the key file,
+    // so each deployment can pick its own
+    rfm.sync(&keys.sync_words).map_err(radio_err("setting sync words"))?;
     rfm.packet(PacketConfig { format: PacketFormat::Variable(64),
                                           dc: PacketDc::Whitening,
                                           crc: true,
                                           filtering: PacketFiltering::None,
-                                          interpacket_rx_delay: InterPacketRxDelay::Delay1Bit, // ???
-                                          auto_rx_restart: true })
-                                          .expect("Radio error setting packet format");
-    rfm.fifo_mode(FifoMode::NotEmpty).expect("Radio error setting FIFO mode");
-    rfm.rx_bw(RxBw { dcc_cutoff: DccCutoff::Percent0dot125, rx_bw: RxBwFsk::Khz25dot0 }).expect("Radio error setting Rx BW");
-    rfm.rx_afc_bw(RxBw { dcc_cutoff: DccCutoff::Percent0dot125, rx_bw: RxBwFsk::Khz25dot0 }).expect("Radio error setting AFC BW");
-    rfm.aes(&ENCRYPTION_KEY).expect("Radio error setting AES key"); // defined in encryption_key.rs
+                                          interpacket_rx_delay,  // see RadioConfig for these two
+                                          auto_rx_restart: config.auto_rx_restart })
+                                          .map_err(radio_err("setting packet format"))?;
+    // hand over packets that fail their CRC instead of having the chip drop
+    // them without a word, so they can be counted (see Rfm69Radio::read_packet)
+    let packet_config = rfm.read(Registers::PacketConfig1).map_err(radio_err("reading PacketConfig1"))?;
+    rfm.write(Registers::PacketConfig1, packet_config | CRC_AUTO_CLEAR_OFF).map_err(radio_err("setting CrcAutoClearOff"))?;
+    rfm.fifo_mode(FifoMode::NotEmpty).map_err(radio_err("setting FIFO mode"))?;
+    // DIO0 mapping 01 is PayloadReady in Rx mode (PacketSent in Tx)
+    rfm.write(Registers::DioMapping1, 0b01 << 6).map_err(radio_err("setting DIO mapping"))?;
+    rfm.rx_bw(RxBw { dcc_cutoff: DccCutoff::Percent0dot125, rx_bw: RxBwFsk::Khz25dot0 }).map_err(radio_err("setting Rx BW"))?;
+    rfm.rx_afc_bw(RxBw { dcc_cutoff: DccCutoff::Percent0dot125, rx_bw: RxBwFsk::Khz25dot0 }).map_err(radio_err("setting AFC BW"))?;
+    // run AFC on every packet, and keep its result around afterwards so it can be read back
+    rfm.write(Registers::AfcFei, AFC_AUTO_ON).map_err(radio_err("turning on AFC"))?;
+    if let Some(key) = aes_key {
+        rfm.aes(key).map_err(radio_err("setting AES key"))?;
+    }
     // rfm69 library never appears to set power level
-    rfm.write(Registers::PaLevel, 0b011_11111).expect("Radio error setting power level"); // power level 17
-    // TODO set up aes encryption
-    // debug - register dump
-    // Print content of all RFM registers
-    // for (index, val) in rfm.read_all_regs().ok().unwrap().iter().enumerate() {
-    //     println!("Register 0x{:02x} = 0x{:02x}", index + 1, val);
-    // }
-    // check for good connection by reading back version register
-    // see https://github.com/adafruit/Adafruit_CircuitPython_RFM69/blob/ad33b2948a13df1c0e036605ef1fb5e6484ea97e/adafruit_rfm69.py#L263
-    match rfm.read(Registers::Version) {
-        Ok(i) => {
-            println!("RFM69 version: 0x{:02x}", i);
-            if i != 0x24 {
-                panic!("Expected version 0x24, exiting.");
-            }
-        },
-        Err(e) => panic!("Error connecting to RFM69: {:#?}", e)
+    rfm.write(Registers::PaLevel, pa_level).map_err(radio_err("setting power level"))?;
+    // register dump, only at trace level since it's a lot of SPI traffic
+    if log_enabled!(Level::Trace) {
+        for line in register_table(&read_registers(rfm).chain_err(|| ErrorKind::RadioError("reading registers".into()))?) {
+            trace!("{}", line);
+        }
     }
-    println!("Carrier frequency: {} MHz", get_frequency(&mut rfm));
-    Ok(rfm)
+    Ok(())
 }
 
 // get the carrier frequency currently set in the RFM69
-fn get_frequency(rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>) -> u32 {
-    (u32::from(rfm.read(Registers::FrfMsb).unwrap()) << 16 |
-     u32::from(rfm.read(Registers::FrfMid).unwrap()) << 8 |
-     u32::from(rfm.read(Registers::FrfLsb).unwrap())) * 61
+fn get_frequency(rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>) -> Result<u32> {
+    Ok((u32::from(rfm.read(Registers::FrfMsb).map_err(radio_err("reading FrfMsb"))?) << 16 |
+        u32::from(rfm.read(Registers::FrfMid).map_err(radio_err("reading FrfMid"))?) << 8 |
+        u32::from(rfm.read(Registers::FrfLsb).map_err(radio_err("reading FrfLsb"))?)) * 61)
+}
+
+// Temp1 register bits and the coarse calibration for Temp2, which counts down
+// 1 per degree C. the offset is the one Adafruit and LowPowerLab use; the
+// datasheet only promises the reading is within a few degrees.
+const TEMP_MEAS_START: u8 = 0b0000_1000;
+const TEMP_MEAS_RUNNING: u8 = 0b0000_0100;
+const TEMP_OFFSET: i16 = 165;
+const TEMP_POLLS: u32 = 100;  // conversion takes <100us, so this is plenty
+
+// read the RFM69's onboard temperature sensor, in degrees C. the sensor only
+// works in standby, so this leaves the radio there; the next recv() puts it
+// back in receive mode.
+fn read_radio_temperature(rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>) -> Result<i8> {
+    rfm.mode(Mode::Standby).map_err(radio_err("entering standby for temperature"))?;
+    rfm.write(Registers::Temp1, TEMP_MEAS_START).map_err(radio_err("starting temperature measurement"))?;
+    let mut polls = 0;
+    while rfm.read(Registers::Temp1).map_err(radio_err("reading Temp1"))? & TEMP_MEAS_RUNNING != 0 {
+        polls += 1;
+        if polls == TEMP_POLLS {
+            return Err(ErrorKind::RadioError("temperature measurement never finished".into()).into());
+        }
+        thread::sleep(time::Duration::from_millis(1));
+    }
+    let value = rfm.read(Registers::Temp2).map_err(radio_err("reading Temp2"))?;
+    Ok((TEMP_OFFSET - i16::from(value)) as i8)
+}
+
+// AfcFei register bit that has the RFM69 correct its frequency for the
+// incoming signal at the start of every reception
+const AFC_AUTO_ON: u8 = 0b0000_0100;
+
+// PacketConfig1 bit that keeps a packet with a bad CRC in the FIFO, and
+// still raises PayloadReady for it
+const CRC_AUTO_CLEAR_OFF: u8 = 0b0000_1000;
+
+// the frequency correction AFC applied for the last packet received, in Hz:
+// how far the rover's crystal is from ours. one that keeps growing is an early
+// sign of a drifting transmitter.
+fn read_afc_offset(rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>) -> Result<i32> {
+    let msb = rfm.read(Registers::AfcMsb).map_err(radio_err("reading AfcMsb"))?;
+    let lsb = rfm.read(Registers::AfcLsb).map_err(radio_err("reading AfcLsb"))?;
+    // two's complement, in units of the synthesizer step
+    Ok((f32::from(i16::from_be_bytes([msb, lsb])) * FSTEP_HZ).round() as i32)
+}
+
+// show the fields an operator cares about most on the OLED, laid out by the
+// display template (see display_template.rs)
+fn display_telemetry(disp: &mut TextDisplay, rows: &[String]) -> Result<()> {
+    disp.clear().map_err(display_err("clearing display"))?;
+    for (row, text) in rows.iter().enumerate() {
+        disp.set_position(0, row as u8).map_err(display_err("positioning display cursor"))?;
+        write!(disp, "{:.*}", DISPLAY_COLUMNS, text).map_err(display_err("writing telemetry to display"))?;
+    }
+    Ok(())
+}
+
+// show how long it's been since the last good packet on the bottom line of
+// the OLED, switching to a warning once it's been long enough that the rover
+// has probably gone out of range
+fn display_link_status(disp: &mut Option<OledDisplay>, last_packet: time::Instant) {
+    let elapsed = last_packet.elapsed().as_secs();
+    write_display(disp, |disp| match disp {
+        OledDisplay::Text(disp) => {
+            let status = if elapsed > LINK_LOST_SECS {
+                String::from("** LINK LOST **")
+            } else {
+                format!("Last pkt {}s", elapsed)
+            };
+            disp.set_position(0, 3).map_err(display_err("positioning display cursor"))?;
+            write!(disp, "{:<1$}", status, DISPLAY_COLUMNS).map_err(display_err("writing link status to display"))
+        },
+        // this runs every time round the loop, so only redraw when it changes
+        OledDisplay::Graphics(disp, gauges) => {
+            let status = if elapsed > LINK_LOST_SECS { String::from("LINK LOST") } else { format!("Last pkt {}s", elapsed) };
+            if gauges.link(status) { draw_gauges(disp, gauges) } else { Ok(()) }
+        }
+    });
+}
+
+// show the recent min/mean/max signal strength on the third line of the OLED
+fn display_link_stats(disp: &mut Option<OledDisplay>, stats: &LinkStats) {
+    write_display(disp, |disp| match (disp, stats.min_rssi(), stats.mean_rssi(), stats.max_rssi()) {
+        (OledDisplay::Text(disp), Some(min), Some(mean), Some(max)) => {
+            disp.set_position(0, 2).map_err(display_err("positioning display cursor"))?;
+            write!(disp, "{:<1$}", format!("RSSI {:.0}/{:.0}/{:.0}", min, mean, max), DISPLAY_COLUMNS)
+                .map_err(display_err("writing link stats to display"))
+        },
+        // the signal bars go by the mean
+        (OledDisplay::Graphics(disp, gauges), _, Some(mean), _) => {
+            gauges.rssi(mean);
+            draw_gauges(disp, gauges)
+        },
+        _ => Ok(())  // nothing received yet
+    });
+}
+
+// periodic link summary for the console
+fn print_link_stats(stats: &LinkStats) {
+    if let (Some(min), Some(mean), Some(max)) = (stats.min_rssi(), stats.mean_rssi(), stats.max_rssi()) {
+        info!("Signal strength min/mean/max: {:.0}/{:.0}/{:.0} dBm", min, mean, max);
+    }
+    if let Some(temperature) = stats.radio_temperature() {
+        info!("Radio temperature: {} C", temperature);
+    }
+    if let Some(rtt) = stats.rtt() {
+        info!("Round trip time: {} ms", rtt.as_millis());
+    }
+    if let Some(offset) = stats.afc_offset() {
+        info!("AFC offset: {} Hz", offset);
+    }
+    if let Some(rate) = stats.crc_failure_rate() {
+        info!("CRC failures: {} ({:.1}%)", stats.crc_failures(), rate * 100.0);
+    }
+    print_message_stats(stats);
+}
+
+// how many of each kind of message the rover has sent, as a table
+fn print_message_stats(stats: &LinkStats) {
+    let messages = &stats.messages;
+    let rows = messages.received().map(|(id, count)| (RoverMessage::get_message_type(id), count))
+        .chain([("malformed", messages.malformed()), ("unknown", messages.unknown())]);
+    for (message_type, count) in rows {
+        info!("{:<26} {:>6}", message_type, count);
+    }
+}
+
+// how received telemetry is written to stdout. everything else goes to
+// stderr, so with Json stdout can be piped straight into jq or the like.
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    Text,  // one line summary per packet
+    Json,  // one JSON object per line
+}
+
+// without a fix, the dead reckoning estimate (if there is one) is tacked on
+// the end of the text summary, marked as such
+fn print_telemetry(telemetry: &RoverMessage, format: OutputFormat, config: &Config, estimate: Option<Estimate>) -> Result<()> {
+    let summary = telemetry.summary(config.coordinate_format);
+    match format {
+        OutputFormat::Text => match (telemetry, config.station, estimate) {
+            // the range and bearing are what an operator with a directional antenna wants
+            (RoverMessage::TelemetryMessage { location, .. }, Some(station), _) if location.has_fix() => {
+                let (distance, bearing) = location.distance_bearing_from(station.lat, station.lon);
+                println!("{} range={:.0}m bearing={:.0}", summary, distance, bearing);
+            },
+            (_, _, Some(estimate)) =>
+                println!("{} ESTIMATED lat={} lon={} ({}s since last fix)", summary,
+                         format_lat(estimate.lat, config.coordinate_format),
+                         format_long(estimate.lon, config.coordinate_format), estimate.since_fix_secs),
+            _ => println!("{}", summary)
+        },
+        OutputFormat::Json => println!("{}", serde_json::to_string(telemetry)?)
+    }
+    Ok(())
 }
 
-fn process_telemetry(telemetry: &RoverMessage) {
+// everywhere received telemetry goes besides the console and the OLED
+struct TelemetrySinks {
+    logger: TelemetryLogger,
+    gpx: GpxWriter,
+    mqtt: Option<MqttPublisher>,
+    influx: Option<InfluxExporter>,
+    latest: Option<LatestTelemetry>,  // served over HTTP
+    websocket: Option<TelemetryFeed>,  // pushed to WebSocket clients
+    memory: MemoryWatch,              // not a sink, but it needs to see every frame too
+    dead_reckoning: DeadReckoning,    // and so does this
+    mission: MissionTracker,          // and this, for the report at the end
+    clock: Option<ClockWatch>,        // the rover's clock against ours, if there's a limit set
+    alerts: Option<Alerter>,
+    dashboard: Option<Dashboard>,     // which takes the place of printing to stdout
+    throttle: Throttle,               // how often the log, MQTT and InfluxDB get a record
+}
+
+impl TelemetrySinks {
+    fn new(config: &Config, latest: Option<LatestTelemetry>) -> Result<TelemetrySinks> {
+        Ok(TelemetrySinks { logger: TelemetryLogger::new(&config.log.telemetry_path)?,
+                            gpx: GpxWriter::new(),
+                            mqtt: config.mqtt.as_ref().map(MqttPublisher::new),
+                            influx: config.influxdb.as_ref().map(|influx| InfluxExporter::new(influx, config.radio.rover_address)),
+                            latest,
+                            websocket: None,
+                            memory: MemoryWatch::new(config.low_memory_bytes, config.memory_trend_frames),
+                            dead_reckoning: DeadReckoning::new(),
+                            mission: MissionTracker::new(),
+                            clock: config.clock_drift_secs.map(ClockWatch::new),
+                            alerts: config.alerts.as_ref().map(Alerter::new),
+                            dashboard: None,
+                            throttle: Throttle::new(config.record_interval_ms.map(time::Duration::from_millis)) })
+    }
+}
+
+// disp is None when there's no OLED, and when replaying a capture, which may
+// well be on a machine without one
+fn process_telemetry(telemetry: &RoverMessage, format: OutputFormat, config: &Config,
+                     disp: &mut Option<OledDisplay>, sinks: &mut TelemetrySinks) {
     match telemetry {
-        RoverMessage::TelemetryMessage { .. }
-            => println!("Telemetry packet received:\n{:#?}", telemetry),
-        _ => println!("Wrong message type received in process_telemetry:\n{:#?}", telemetry)
+        RoverMessage::TelemetryMessage { timestamp, location, free_memory, battery_millivolts, .. } => {
+            let estimate = sinks.dead_reckoning.update(timestamp, location);
+            if let Some(clock) = &mut sinks.clock {
+                clock.check(timestamp, chrono::Utc::now());
+            }
+            match &sinks.dashboard {
+                Some(dashboard) => dashboard.telemetry(telemetry, estimate, config.coordinate_format),
+                None => if let Err(e) = print_telemetry(telemetry, format, config, estimate) {
+                    error!("Error printing telemetry: {}", e);
+                }
+            }
+            let memory = sinks.memory.update(*free_memory);
+            if let Some(alerts) = &mut sinks.alerts {
+                alerts.check_memory(memory != MemoryState::Ok, *free_memory);
+                alerts.check_battery(*battery_millivolts);
+            }
+            let rows = config.display.render(telemetry, memory, config.coordinate_format);
+            write_display(disp, |disp| match disp {
+                OledDisplay::Text(disp) => display_telemetry(disp, &rows),
+                OledDisplay::Graphics(disp, gauges) => {
+                    gauges.telemetry(location.gps_hdg, rows.clone());
+                    draw_gauges(disp, gauges)
+                }
+            });
+            sinks.gpx.add_point(location, timestamp);
+            sinks.mission.record(timestamp, location);
+            // everything up to here sees every frame; what's written out or
+            // sent elsewhere is held to the record interval
+            if sinks.throttle.ready() {
+                if let Err(e) = sinks.logger.log(telemetry) {
+                    error!("Error logging telemetry: {}", e);
+                }
+                if let Some(mqtt) = &mut sinks.mqtt {
+                    if let Err(e) = mqtt.publish(telemetry) {
+                        warn!("Error publishing telemetry: {}", e);
+                    }
+                }
+                if let Some(influx) = &mut sinks.influx {
+                    if let Err(e) = influx.export(telemetry) {
+                        warn!("Error exporting telemetry: {}", e);
+                    }
+                }
+            }
+            if let Some(latest) = &sinks.latest {
+                match latest.lock() {
+                    Ok(mut latest) => *latest = Some(telemetry.clone()),
+                    Err(_) => error!("Error sharing telemetry: lock poisoned")
+                }
+            }
+            if let Some(websocket) = &sinks.websocket {
+                if let Err(e) = websocket.publish(telemetry) {
+                    warn!("Error pushing telemetry to WebSocket clients: {}", e);
+                }
+            }
+        },
+        _ => warn!("Wrong message type received in process_telemetry: {}", telemetry)
+    }
+}
+
+// the rover answers a TelemetryAck with command_waiting set by sending
+// CommandReady. once it has, send whatever is queued up, or if nothing is,
+// read commands from stdin a line at a time and send each one to the rover,
+// until the operator enters an empty line, which is sent as the end of the
+// sequence.
+fn command_session<R: RadioLink>(rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats,
+                                 queue: &mut CommandQueue) -> Result<()> {
+    let mut session = CommandSession::new();
+    if !queue.is_empty() {
+        return session.run_session(rfm, config, stats, queue);
+    }
+    session.await_ready(rfm, config, stats)?;
+    println!("Rover ready for commands; enter an empty line to finish");
+    let stdin = io::stdin();
+    while session.state() != SessionState::Complete {
+        print!("command> ");
+        io::stdout().flush()?;
+        // end of input finishes the sequence the same as an empty line
+        let mut line = String::new();
+        let end_of_input = stdin.lock().read_line(&mut line)? == 0;
+        let command = line.trim();
+        match session.send_command(rfm, config, stats, command, command.is_empty()) {
+            Ok(_) => (),
+            Err(e @ Error(ErrorKind::RadioError(_), _)) => return Err(e),
+            // nobody left to try again
+            Err(e) if end_of_input => return Err(e),
+            // too long, refused or never acked: let the operator try again
+            Err(e) => warn!("Command not sent: {}", e.display_chain())
+        }
+    }
+    Ok(())
+}
+
+// clear out the OLED and leave a message saying we're gone, so it doesn't
+// keep showing stale telemetry after the station has stopped
+fn display_shutdown(disp: Option<&mut OledDisplay>) -> Result<()> {
+    match disp {
+        Some(disp) => display_message(disp, "Shutting down").chain_err(|| "writing shutdown message"),
+        None => Ok(())
+    }
+}
+
+// ask the rover for a TelemetryMessage right now instead of waiting for one
+fn poll_telemetry<R: RadioLink>(rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats) -> Result<RoverMessage> {
+    let request = RoverMessage::request_telemetry();
+    request.send(rfm, config, stats)?
+        .ok_or_else(|| ErrorKind::ReceiveError("no reply to RequestTelemetry".into()).into())
+}
+
+// count a radio error, and once there have been too many in a row, try
+// resetting the radio rather than carrying on with one that's wedged. only a
+// failed reset is fatal.
+fn radio_trouble(radio: &mut StationRadio, config: &RadioConfig, errors: &mut u32, e: Error) -> Result<()> {
+    warn!("{}", e.display_chain());
+    *errors += 1;
+    if *errors >= RADIO_ERROR_LIMIT {
+        warn!("{} radio errors in a row, resetting the radio", errors);
+        radio.restart(config)?;
+        *errors = 0;
+    }
+    Ok(())
+}
+
+// the longest one time round the receive loop can take with nothing wrong:
+// listening for the rover and acking what it sends, a poll and a ping if
+// they're on, reading the temperature, and resetting the radio (twice, if it
+// doesn't come back the first time). command sessions don't count, since the
+// watchdog is paused for them.
+fn longest_loop(config: &Config, poll: bool) -> time::Duration {
+    let send = longest_send(&config.radio);
+    let receive = time::Duration::from_millis(RECEIVE_TIMEOUT_MS) + send;
+    let poll = if poll { send } else { time::Duration::ZERO };
+    let ping = if config.ping_interval_secs.is_some() { send } else { time::Duration::ZERO };
+    let temperature = time::Duration::from_millis(u64::from(TEMP_POLLS));
+    let reset = time::Duration::from_millis(2 * (RESET_PULSE_MS + RESET_SETTLE_MS));
+    receive + poll + ping + temperature + reset
+}
+
+// feed frames captured in the field through the same deserialize and
+// process_telemetry path live packets take, without touching the radio
+fn run_from_capture(path: &Path, config: &Config, format: OutputFormat) -> Result<()> {
+    let frames = capture::load(path)?;
+    let mut sinks = TelemetrySinks::new(config, None)?;
+    // nobody needs waking up over something that happened in the field
+    sinks.alerts = None;
+    // and the station's clock now says nothing about the rover's then
+    sinks.clock = None;
+    // and the frames come through far faster than they did then
+    sinks.throttle = Throttle::new(None);
+    for (number, frame) in frames.into_iter().enumerate() {
+        dump_frame("replayed", &frame);
+        // acks, pongs and the like get captured too, but only telemetry is replayed
+        match peek_message_id(&frame) {
+            Some("MESSAGE_TELEMETRY") | None => (),
+            Some(message_type) => {
+                debug!("Frame {}: skipping {}", number + 1, message_type);
+                continue;
+            }
+        }
+        let mut telemetry = RoverMessage::empty_telemetry();
+        match telemetry.deserialize(&frame) {
+            Ok(_) => process_telemetry(&telemetry, format, config, &mut None, &mut sinks),
+            Err(e) => warn!("Frame {}: {}", number + 1, e.display_chain())
+        }
+    }
+    sinks.logger.flush()?;
+    sinks.gpx.finish(&config.log.gpx_path)?;
+    Ok(())
+}
+
+// made up telemetry through process_telemetry on a timer, for checking the
+// outputs over without a rover. it's all served and published as if it were
+// real (it says SYNTHETIC in the status), but no alerts go off over it.
+fn run_synthetic(config: &Config, format: OutputFormat) -> Result<()> {
+    let mut sinks = serving_sinks(config)?;
+    sinks.alerts = None;
+    let running = stop_on_ctrlc()?;
+    let mut generator = TelemetryGenerator::new(&config.synthetic);
+    info!("Injecting synthetic telemetry every {} ms; Ctrl-C to stop", config.synthetic.interval_ms);
+    while running.load(Ordering::SeqCst) {
+        process_telemetry(&generator.next_telemetry(), format, config, &mut None, &mut sinks);
+        thread::sleep(time::Duration::from_millis(config.synthetic.interval_ms));
+    }
+    sinks.logger.flush()?;
+    sinks.gpx.finish(&config.log.gpx_path)?;
+    Ok(())
+}
+
+// the sinks for telemetry coming in as it happens, with the HTTP and
+// WebSocket servers started if they're asked for (they're off otherwise)
+fn serving_sinks(config: &Config) -> Result<TelemetrySinks> {
+    let latest = match config.http_port {
+        Some(port) => {
+            let latest = LatestTelemetry::default();
+            http_server::start(port, latest.clone())?;
+            Some(latest)
+        },
+        None => None
+    };
+    let mut sinks = TelemetrySinks::new(config, latest)?;
+    sinks.websocket = config.websocket.as_ref().map(websocket::start).transpose()?;
+    Ok(sinks)
+}
+
+// Ctrl-C just clears the flag, for the loop to notice
+fn stop_on_ctrlc() -> Result<Arc<AtomicBool>> {
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_running = running.clone();
+    ctrlc::set_handler(move || handler_running.store(false, Ordering::SeqCst))
+        .chain_err(|| "installing Ctrl-C handler")?;
+    Ok(running)
+}
+
+// check the radio over before a mission, without needing the rover: each
+// step prints PASS or FAIL, and any failure makes the whole thing an error.
+// a Ping nobody answers is fine, since the rover may well be off, but one
+// that can't be sent isn't.
+fn run_selftest(config: &RadioConfig, listen_secs: u64) -> Result<()> {
+    let mut radio = match setup_radio(config) {
+        Ok(radio) => radio,
+        Err(e) => {
+            println!("FAIL setup: {}", one_line(&e));
+            return Err(e).chain_err(|| "self test failed");
+        }
+    };
+    println!("PASS setup: radio set up at {} MHz", config.frequency_hz / 1e6);
+    let results = [("version", check_version(&mut radio.rfm).map(|_| "RFM69 found".into())),
+                   ("temperature", read_radio_temperature(&mut radio.rfm).map(|t| format!("{} C", t))),
+                   ("transmit", match send_ping(&mut radio, config, &mut LinkStats::new()) {
+                       Ok(rtt) => Ok(format!("sent a Ping; the rover answered in {} ms", rtt.as_millis())),
+                       Err(Error(ErrorKind::SendError(_), _)) => Ok("sent a Ping; no answer, which is fine if the rover is off".into()),
+                       Err(e) => Err(e)
+                   }),
+                   ("listen", listen_for_packets(&mut radio, listen_secs))];
+    let failed: Vec<&str> = results.iter().filter(|(step, result)| !report(step, result)).map(|(step, _)| *step).collect();
+    if !failed.is_empty() {
+        return Err(format!("self test failed: {}", failed.join(", ")).into());
+    }
+    println!("Self test passed");
+    Ok(())
+}
+
+// set the radio up as usual and print what ended up in its registers, to
+// compare with the rover's
+fn run_dump_registers(config: &RadioConfig) -> Result<()> {
+    let mut radio = setup_radio(config)?;
+    let registers = read_registers(&mut radio.rfm).chain_err(|| ErrorKind::RadioError("reading registers".into()))?;
+    println!("Addr  Register         Hex   Binary");
+    for line in register_table(&registers) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// print how a self test step went, returning whether it passed
+fn report(step: &str, result: &Result<String>) -> bool {
+    match result {
+        Ok(detail) => println!("PASS {}: {}", step, detail),
+        Err(e) => println!("FAIL {}: {}", step, one_line(e))
     }
+    result.is_ok()
+}
+
+// an error and everything that caused it
+fn one_line(e: &Error) -> String {
+    e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ")
 }
 
-fn run() -> Result<()> {
-    let mut disp = setup_display().unwrap();
-    disp.write_str("Rover Ground\nControl v0.1").expect("Display error writing welcome message");
-    let mut rfm = setup_radio().unwrap();
+// the noise floor, then whatever turns up in the next few seconds
+fn listen_for_packets(radio: &mut Rfm69Radio, secs: u64) -> Result<String> {
+    let noise = radio.channel_rssi()?;
+    let mut buf = [0; 64];
+    let mut heard: Vec<f32> = Vec::new();
+    let start = time::Instant::now();
+    while start.elapsed().as_secs() < secs {
+        if radio.recv(&mut buf, time::Duration::from_millis(100))? > 0 {
+            heard.push(radio.rssi());
+        }
+    }
+    Ok(match heard.iter().cloned().fold(None, |max: Option<f32>, rssi| Some(max.map_or(rssi, |m| m.max(rssi)))) {
+        Some(strongest) => format!("noise floor {} dBm; heard {} frames, the strongest at {} dBm", noise, heard.len(), strongest),
+        None => format!("noise floor {} dBm; heard nothing in {} s", noise, secs)
+    })
+}
+
+fn run(args: Args, dashboard: Option<Dashboard>) -> Result<()> {
+    let mut config = args.config()?;
+    let output_format = if args.json { OutputFormat::Json } else { OutputFormat::Text };
+    match &args.subcommand {
+        Some(Command::Replay { path }) => return run_from_capture(path, &config, output_format),
+        Some(Command::Selftest { listen }) => return run_selftest(&config.radio, *listen),
+        Some(Command::DumpRegisters) => return run_dump_registers(&config.radio),
+        Some(Command::InjectTelemetry) => return run_synthetic(&config, output_format),
+        None => ()
+    }
+    config.display.check()?;
+    // rather than find out at the first ack
+    config.radio.send_to()?;
+    // a headless station (stdout, JSON, the dashboard) can do without the OLED
+    let mut disp = match setup_display(config.display.mode) {
+        Ok(disp) => Some(disp),
+        Err(e) => {
+            warn!("Carrying on without the display: {}", e.display_chain());
+            None
+        }
+    };
+    if let Some(disp) = &mut disp {
+        display_message(disp, &config.display.banner()).chain_err(|| "writing welcome message")?;
+    }
+    let mut radio = StationRadio::open(&config)?;
+    let mut sinks = serving_sinks(&config)?;
+    // Ctrl-C just asks the loop to stop; it's checked between packets, so
+    // shutdown can take up to one receive timeout
+    let running = stop_on_ctrlc()?;
+    // the dashboard watches for q and Ctrl-C itself, since the terminal stops
+    // sending signals once it's in raw mode. it goes away with screen.
+    let screen = match &dashboard {
+        Some(dashboard) => Some(dashboard.show(running.clone())?),
+        None => None
+    };
+    sinks.dashboard = dashboard;
+    let mut link_stats = LinkStats::new();
+    if let Some(path) = &args.capture {
+        link_stats.capture = Some(FrameCapture::new(path)?);
+    }
+    if let Some(path) = &config.log.signal_path {
+        link_stats.signal_log = Some(SignalLogger::new(path)?);
+    }
+    // wherever the rover turns up is where the radio stays, resets included
+    if args.scan {
+        if let Some(frequency_hz) = scan(&mut radio, &config, &mut link_stats, &running)? {
+            config.radio.frequency_hz = frequency_hz;
+        }
+    }
+    let radio_config = &config.radio;
+    let mut radio_errors = 0;
+    let mut last_packet = time::Instant::now();
+    // start out due for a reading
+    let mut last_temperature = time::Instant::now() - time::Duration::from_secs(TEMPERATURE_INTERVAL_SECS);
+    let mut last_ping = time::Instant::now();
+    // with --command, every telemetry ack asks the rover for a command session
+    // and so does anything queued up from --commands, until it's been sent
+    let mut queue = match &args.commands {
+        Some(path) => CommandQueue::load(path)?,
+        None => CommandQueue::new()
+    };
+    // the watchdog is off unless asked for, and has to give the loop longer
+    // than it can take when nothing's wrong
+    if let Some(secs) = config.watchdog_secs {
+        let longest = longest_loop(&config, args.poll);
+        if time::Duration::from_secs(secs) <= longest {
+            return Err(format!("watchdog_secs must be more than the {:.1} s one time round the receive loop can take",
+                               longest.as_secs_f32()).into());
+        }
+    }
+    let watchdog = Watchdog::start(config.watchdog_secs.map(time::Duration::from_secs));
     // loop and receive telemetry packets
-    loop {
-        let mut telemetry: RoverMessage = RoverMessage::TelemetryMessage { timestamp: Default::default(),
-                                                                           location: Default::default(),
-                                                                           signal_strength: 0,
-                                                                           free_memory: 0,
-                                                                           status: String::new() };
-        match telemetry.receive(&mut rfm, 10000) {
-            Ok(()) => process_telemetry(&telemetry),
-            Err(e) => println!("{:#?}", e)
+    while running.load(Ordering::SeqCst) {
+        // the same reset as after too many radio errors
+        if watchdog.tripped() {
+            warn!("The receive loop hung, resetting the radio");
+            radio.restart(radio_config)?;
+            radio_errors = 0;
+        }
+        watchdog.beat();
+        let command_mode = args.command || !queue.is_empty();
+        match RoverMessage::receive_any(&mut radio, radio_config, &mut link_stats, RECEIVE_TIMEOUT_MS, command_mode) {
+            Ok((message, _)) => {
+                last_packet = time::Instant::now();
+                radio_errors = 0;
+                if let StationRadio::Bonnet(radio) = &mut radio {
+                    match read_afc_offset(&mut radio.rfm) {
+                        Ok(offset) => {
+                            debug!("AFC offset: {} Hz", offset);
+                            link_stats.record_afc_offset(offset);
+                        },
+                        Err(e) => warn!("{}", e.display_chain())
+                    }
+                }
+                // the rover only ever starts a conversation with telemetry
+                // (which is what gets acked with command_waiting)
+                if let RoverMessage::TelemetryMessage { .. } = message {
+                    process_telemetry(&message, output_format, &config, &mut disp, &mut sinks);
+                    display_link_stats(&mut disp, &link_stats);
+                    if command_mode {
+                        watchdog.pause();
+                        let session = command_session(&mut radio, radio_config, &mut link_stats, &mut queue);
+                        watchdog.beat();
+                        match session {
+                            Ok(()) => (),
+                            Err(e @ Error(ErrorKind::RadioError(_), _)) => radio_trouble(&mut radio, radio_config, &mut radio_errors, e)?,
+                            Err(e) => warn!("Command session failed: {}", e.display_chain())
+                        }
+                    }
+                } else {
+                    warn!("Unexpected message from rover: {}", message);
+                }
+            },
+            // the radio itself is in trouble
+            Err(e @ Error(ErrorKind::RadioError(_), _)) => radio_trouble(&mut radio, radio_config, &mut radio_errors, e)?,
+            // timeouts and bad packets: keep listening
+            Err(e) => {
+                warn!("{}", e.display_chain());
+                if args.poll {
+                    match poll_telemetry(&mut radio, radio_config, &mut link_stats) {
+                        Ok(telemetry) => {
+                            last_packet = time::Instant::now();
+                            radio_errors = 0;
+                            process_telemetry(&telemetry, output_format, &config, &mut disp, &mut sinks);
+                            display_link_stats(&mut disp, &link_stats);
+                        },
+                        Err(e @ Error(ErrorKind::RadioError(_), _)) => radio_trouble(&mut radio, radio_config, &mut radio_errors, e)?,
+                        Err(e) => warn!("{}", e.display_chain())
+                    }
+                }
+            }
+        }
+        display_link_status(&mut disp, last_packet);
+        if let Some(dashboard) = &sinks.dashboard {
+            dashboard.link(&link_stats, last_packet);
+        }
+        if let Some(alerts) = &mut sinks.alerts {
+            alerts.check_link(last_packet.elapsed());
+        }
+        if last_temperature.elapsed().as_secs() >= TEMPERATURE_INTERVAL_SECS {
+            // only a diagnostic, but a radio that can't manage it is in trouble
+            let reading = match &mut radio {
+                StationRadio::Bonnet(radio) => Some(read_radio_temperature(&mut radio.rfm)),
+                StationRadio::Serial(_) => None
+            };
+            match reading {
+                Some(Ok(temperature)) => link_stats.record_radio_temperature(temperature),
+                Some(Err(e)) => radio_trouble(&mut radio, radio_config, &mut radio_errors, e)?,
+                None => ()
+            }
+            print_link_stats(&link_stats);
+            last_temperature = time::Instant::now();
+        }
+        if let Some(interval) = config.ping_interval_secs {
+            if last_ping.elapsed().as_secs() >= interval {
+                match send_ping(&mut radio, radio_config, &mut link_stats) {
+                    Ok(rtt) => info!("Ping: {} ms", rtt.as_millis()),
+                    Err(e @ Error(ErrorKind::RadioError(_), _)) => radio_trouble(&mut radio, radio_config, &mut radio_errors, e)?,
+                    Err(e) => warn!("Ping failed: {}", e.display_chain())
+                }
+                last_ping = time::Instant::now();
+            }
         }
     }
+    // back to the plain console, so the last of the output stays on the screen
+    drop(screen);
+    info!("Shutting down");
+    print_message_stats(&link_stats);
+    display_shutdown(disp.as_mut())?;
+    sinks.logger.flush()?;
+    sinks.gpx.finish(&config.log.gpx_path)?;
+    sinks.mission.report(&link_stats).write(&config.log.report_path)?;
+    Ok(())
 }
 
 fn main() {
-    if let Err(ref e) = run() {
-        println!("error: {}", e);
+    let args = Args::parse();
+    // info and up unless RUST_LOG says otherwise
+    let mut logging = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    // the dashboard only shows the live station, not replays or self tests
+    let dashboard = match (&args.subcommand, args.tui) {
+        (None, true) => Some(Dashboard::new(LINK_LOST_SECS)),
+        _ => None
+    };
+    match &dashboard {
+        Some(dashboard) => dashboard::init_logging(&mut logging, dashboard),
+        None => logging.init()
+    }
+    if let Err(ref e) = run(args, dashboard) {
+        error!("error: {}", e);
         for e in e.iter().skip(1) {
-            println!("caused by: {}", e);
+            error!("caused by: {}", e);
         }
         if let Some(backtrace) = e.backtrace() {
-            println!("backtrace: {:?}", backtrace);
+            error!("backtrace: {:?}", backtrace);
         }
         ::std::process::exit(1);
     }
@@ -0,0 +1,83 @@
+// keeps an eye on the free_memory the rover reports, so a leak shows up at
+// the station before the rover falls over in the field. it's low once it's
+// under a set number of bytes, and falling once it's gone down (or at least
+// not up) in every one of the last few frames.
+
+use log::warn;
+use std::collections::VecDeque;
+
+// it takes two frames to go down at all
+pub const MIN_TREND_FRAMES: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryState {
+    Ok,
+    Low,
+    Falling,
+}
+
+pub struct MemoryWatch {
+    low_bytes: Option<u16>,       // no low warning without a threshold
+    trend_frames: Option<usize>,  // no trend warning without a window
+    recent: VecDeque<u16>,
+    state: MemoryState,
+}
+
+impl MemoryWatch {
+    pub fn new(low_bytes: Option<u16>, trend_frames: Option<usize>) -> MemoryWatch {
+        MemoryWatch { low_bytes,
+                      trend_frames,
+                      recent: VecDeque::new(),
+                      state: MemoryState::Ok }
+    }
+
+    // take the free_memory from a telemetry frame. the warning is only logged
+    // when the state changes, not again for every frame after.
+    pub fn update(&mut self, free_memory: u16) -> MemoryState {
+        if let Some(frames) = self.trend_frames {
+            if self.recent.len() == frames {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(free_memory);
+        }
+        let state = match self.low_bytes {
+            Some(low_bytes) if free_memory < low_bytes => MemoryState::Low,
+            _ if self.falling() => MemoryState::Falling,
+            _ => MemoryState::Ok
+        };
+        if state != self.state {
+            match state {
+                MemoryState::Low => warn!("ROVER MEMORY LOW: {} bytes free", free_memory),
+                MemoryState::Falling => warn!("ROVER MEMORY FALLING: {} bytes free, down from {} over {} frames",
+                                              free_memory, self.recent[0], self.recent.len()),
+                MemoryState::Ok => ()
+            }
+        }
+        self.state = state;
+        state
+    }
+
+    fn falling(&self) -> bool {
+        let full = matches!(self.trend_frames, Some(frames) if frames >= MIN_TREND_FRAMES && self.recent.len() == frames);
+        full && self.recent.iter().zip(self.recent.iter().skip(1)).all(|(before, after)| after <= before)
+             && self.recent.back() < self.recent.front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_and_falling() {
+        let mut watch = MemoryWatch::new(Some(500), Some(3));
+        assert_eq!(watch.update(1000), MemoryState::Ok);
+        assert_eq!(watch.update(900), MemoryState::Ok);
+        assert_eq!(watch.update(900), MemoryState::Falling);
+        assert_eq!(watch.update(950), MemoryState::Ok);
+        assert_eq!(watch.update(400), MemoryState::Low);
+        // without settings it never complains
+        let mut watch = MemoryWatch::new(None, None);
+        assert_eq!(watch.update(0), MemoryState::Ok);
+    }
+}
@@ -2,15 +2,50 @@
 
 use chrono::prelude::*;
 use crate::errors::*;
-use rfm69::{ Rfm69, registers::Registers };
-use rppal::{ gpio::OutputPin, spi::Spi };
+use crate::link_stats::LinkStats;
+use crate::radio::{ ListenBeforeTalk, RadioConfig, RadioLink, Rfm69Error, Timing };
+use log::{ debug, log_enabled, warn, Level };
+use std::convert::TryFrom;
+use std::fmt::{ self, Write };
+use serde::{ Deserialize, Serialize };
 use std::{ thread };
-use std::time::{ Duration, Instant };
+use std::time::{ Duration, Instant, SystemTime };
 
-const ACK_TIMEOUT: u64 = 1000;   // millis to wait for an ack msg
-const MSG_DELAY: u64 = 100;      // millis to wait between Rx and Tx, to give the other side time to switch from Tx to Rx
-const LISTEN_DELAY: u64 = 50;   // millis to wait between checks of the receive buffer when receiving
-const USE_ENCRYPTION: bool = true;
+// the rest of the protocol's timing is configurable, see radio::Timing
+const RETRY_DELAY: u64 = 200;    // millis to back off before a retransmission (multiplied by the retry number)
+
+// wire format version, sent as the byte immediately after the message id. bump
+// this whenever the layout of any message changes so that mismatched rover
+// firmware is rejected instead of misparsed.
+// version 2 added battery_millivolts to TelemetryMessage.
+// version 3 started using the RadioHead ID byte as a sequence number.
+// version 4 added command fragments, marked in the RadioHead FLAGS byte.
+// version 5 added the trailing checksum.
+const PROTOCOL_VERSION: u8 = 5;
+
+// sizes of the fixed parts of every message
+const HEADER_SIZE: usize = 7;     // length byte, TO, FROM, ID, FLAGS, message id, protocol version
+const TIMESTAMP_SIZE: usize = 6;  // see RoverTimestamp
+const LOC_DATA_SIZE: usize = 19;  // see RoverLocData
+const BODY_START: usize = HEADER_SIZE + TIMESTAMP_SIZE;  // every message starts with a timestamp
+const CHECKSUM_SIZE: usize = 1;   // CRC-8 on the end of every message, see crc8()
+const TO_OFFSET: usize = 1;
+const FROM_OFFSET: usize = 2;
+const MESSAGE_ID_OFFSET: usize = 5;
+
+// a command too long for one packet is sent as several CommandMessages, each
+// with its fragment number in the FLAGS byte and all but the last marked as
+// having more to come. the rover glues them back together. RadioHead leaves
+// the low nibble of FLAGS to applications.
+const FLAG_MORE_FRAGMENTS: u8 = 0b0000_1000;
+const FRAGMENT_INDEX_MASK: u8 = 0b0000_0111;
+const MAX_FRAGMENTS: usize = 8;
+
+// mean radius, for distances between GPS fixes
+pub const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// RadioHead's address for "everyone"
+pub const BROADCAST_ADDRESS: u8 = 0xff;
 
 // message IDs for serialization
 const MESSAGE_TELEMETRY: u8 = 0;
@@ -18,38 +53,91 @@ const MESSAGE_TELEMETRY_ACK: u8 = 1;
 const MESSAGE_COMMAND_READY: u8 = 2;
 const MESSAGE_COMMAND: u8 = 3;
 const MESSAGE_COMMAND_ACK: u8 = 4;
+const MESSAGE_REQUEST_TELEMETRY: u8 = 5;
+const MESSAGE_PING: u8 = 6;
+const MESSAGE_PONG: u8 = 7;
+const UNKNOWN_MESSAGE: &str = "MESSAGE_UNKNOWN";
 
-// serialization / deserialization code on this end currently assumes that we will have
-// five extra header bytes on the head of the payload that we need to (for the moment)
-// ignore. RadioHead invisibly deals with these on the rover end but the rfm69 library
-// we use on this end does not take them back off. the first byte is the total payload
-// length (including the five header bytes) and the next four are TO, FROM, ID, FLAGS
-// currently hardcoded to vec![0xff, 0xff, 0x00, 0x00]
-#[derive(Debug)]
+// serialization / deserialization code on this end has to deal with five extra
+// header bytes on the head of the payload. RadioHead invisibly deals with these on
+// the rover end but the rfm69 library we use on this end does not take them back
+// off. the first byte is the total payload length (including the five header
+// bytes) and the next four are TO, FROM, ID, FLAGS - see RadioHeadHeader. after the header comes the
+// message id and then the PROTOCOL_VERSION byte, followed by the message fields
+// and finally a checksum of everything after the length byte.
+// as JSON, the variant name goes in a "type" field alongside the others.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
 pub enum RoverMessage {
     TelemetryMessage { timestamp: RoverTimestamp,  // sent by the rover to communicate location and status.
-                       location: RoverLocData,     // max status length should be 28 ASCII chars with encryption
-                       signal_strength: i16,       // turned on, 219 chars with it turned off
-                       free_memory: u16,
-                       status: String },
+                       location: RoverLocData,     // max status length should be 25 ASCII chars with encryption
+                       signal_strength: i16,       // turned on, 216 chars with it turned off
+                       free_memory: u16,           // signal_strength is the RSSI in dBm of the station's last
+                       battery_millivolts: u16,    // packet as the rover heard it (RadioHead's lastRssi), not
+                       status: String },           // to be confused with the RSSI we measure of the rover's
+                                                   // packets, which goes in LinkStats
 
     TelemetryAck { timestamp: RoverTimestamp,      // sent by the station to acknowledge a TelemetryMessage
                    ack: bool,                      // and possibly tell the rover to switch to command mode
                    command_waiting: bool },        // (if commandWaiting = true). max msg length = 58/249
+                                                   // the timestamp is the station's clock (UTC), read as the
+                                                   // ack goes out; the rover can set its own clock from it
 
     CommandReady { timestamp: RoverTimestamp,      // sent by the rover to indicate it is ready to receive commands.
                    ready: bool },                  // max msg length = 59/250
 
     CommandMessage { timestamp: RoverTimestamp,    // sent by the station to communicate part of a command
                      sequence_complete: bool,      // sequence and possibly tell the rover that the sequence
-                     command: String },            // is complete (if sequenceComplete = true). max command length = 58/249
+                     command: String },            // is complete (if sequenceComplete = true). max command length = 49/240
 
     CommandAck { timestamp: RoverTimestamp,        // sent by the rover to acknowledge a CommandMessage. max msg
                  ack: bool },                      // length = 59/250
+
+    RequestTelemetry { timestamp: RoverTimestamp },  // sent by the station to ask the rover for a
+                                                     // TelemetryMessage right away
+
+    Ping { timestamp: RoverTimestamp },            // sent by the station to time the link; the rover
+                                                   // answers straight away with a Pong
+
+    Pong { timestamp: RoverTimestamp },            // sent by the rover in answer to a Ping
+}
+
+// the four header bytes RadioHead sends after the length byte. ID carries a
+// sequence number that counts up separately for each message type, so that a
+// retransmission (sent because its ack got lost) can be recognized and dropped,
+// and FLAGS carries the command fragment bits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RadioHeadHeader {
+    pub to: u8,
+    pub from: u8,
+    pub id: u8,
+    pub flags: u8,
 }
 
-#[derive(Debug)]
-pub struct RoverTimestamp {  // 6 bytes
+impl RadioHeadHeader {
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.push(self.to);
+        buf.push(self.from);
+        buf.push(self.id);
+        buf.push(self.flags);
+    }
+
+    fn deserialize(&mut self, buf: &mut &[u8]) {
+        self.to = buf[0];
+        self.from = buf[1];
+        self.id = buf[2];
+        self.flags = buf[3];
+    }
+}
+
+impl Default for RadioHeadHeader {
+    fn default() -> Self {
+        Self { to: BROADCAST_ADDRESS, from: BROADCAST_ADDRESS, id: 0, flags: 0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RoverTimestamp {  // TIMESTAMP_SIZE bytes
     pub year: u8,
     pub month: u8,
     pub day: u8,
@@ -76,10 +164,16 @@ impl RoverTimestamp {
         self.minute = buf[4];
         self.second = buf[5];
     }
-}
 
-impl Default for RoverTimestamp {
-    fn default() -> Self {
+    // None if the fields don't make a real date and time
+    pub fn to_datetime(&self) -> Option<DateTime<Utc>> {
+        let time = NaiveDate::from_ymd_opt(2000 + i32::from(self.year), self.month.into(), self.day.into())?
+            .and_hms_opt(self.hour.into(), self.minute.into(), self.second.into())?;
+        Some(Utc.from_utc_datetime(&time))
+    }
+
+    // the station's clock, in UTC
+    pub fn now() -> Self {
         let utc_time: DateTime<Utc> = Utc::now();
         Self {
             year: (utc_time.date().year() - 2000) as u8,
@@ -92,8 +186,21 @@ impl Default for RoverTimestamp {
     }
 }
 
-#[derive(Debug, Default)]
-pub struct RoverLocData { // should serialize to 24 bytes (4x float-32@5, +fixint@1, int-16@3)
+impl Default for RoverTimestamp {
+    fn default() -> Self {
+        RoverTimestamp::now()
+    }
+}
+
+// just the time of day; the date hardly ever changes during a mission
+impl fmt::Display for RoverTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RoverLocData { // serializes to LOC_DATA_SIZE bytes (4x float-32, u8, u16)
     pub gps_lat: f32,
     pub gps_long: f32,
     pub gps_alt: f32,
@@ -103,6 +210,15 @@ pub struct RoverLocData { // should serialize to 24 bytes (4x float-32@5, +fixin
 }
 
 impl RoverLocData {
+    fn serialize(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.gps_lat.to_le_bytes());
+        buf.extend_from_slice(&self.gps_long.to_le_bytes());
+        buf.extend_from_slice(&self.gps_alt.to_le_bytes());
+        buf.extend_from_slice(&self.gps_speed.to_le_bytes());
+        buf.push(self.gps_sats);
+        buf.extend_from_slice(&self.gps_hdg.to_le_bytes());
+    }
+
     fn deserialize(&mut self, buf: &mut &[u8]) {
         self.gps_lat = f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
         self.gps_long = f32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
@@ -110,7 +226,138 @@ impl RoverLocData {
         self.gps_speed = f32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]);
         self.gps_sats = buf[16];
         self.gps_hdg = u16::from_le_bytes([buf[17], buf[18]]);
-    }}
+    }
+
+    // the rover reports 0.0/0.0 until it has a GPS lock
+    pub fn has_fix(&self) -> bool {
+        self.gps_lat != 0.0 || self.gps_long != 0.0
+    }
+
+    // great circle distance in meters and initial bearing in degrees (0-360,
+    // clockwise from true north) from the given point to the rover, by the
+    // haversine formula. check has_fix first; without one this is the way to
+    // 0.0/0.0.
+    pub fn distance_bearing_from(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let (lat1, lat2) = (lat.to_radians(), f64::from(self.gps_lat).to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (f64::from(self.gps_long) - lon).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        let distance = 2.0 * EARTH_RADIUS_M * a.sqrt().asin();
+        let bearing = (d_lon.sin() * lat2.cos()).atan2(lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos());
+        (distance, (bearing.to_degrees() + 360.0) % 360.0)
+    }
+
+    // a garbled packet that still gets past the checksum can decode to a
+    // position like 3e38, which would wreck the GPX track and anything else
+    // downstream, so reject anything that can't be a real fix
+    fn validate(&self) -> Result<()> {
+        if !(-90.0..=90.0).contains(&self.gps_lat) || !(-180.0..=180.0).contains(&self.gps_long) {
+            return Err(ErrorKind::ReceiveError(format!("impossible GPS position {}, {}", self.gps_lat, self.gps_long)).into());
+        }
+        if !self.gps_alt.is_finite() || !self.gps_speed.is_finite() {
+            return Err(ErrorKind::ReceiveError(format!("impossible GPS altitude {} or speed {}", self.gps_alt, self.gps_speed)).into());
+        }
+        Ok(())
+    }
+
+    // gps_hdg as one of the 16 points of the compass, each covering 22.5
+    // degrees centered on its heading, so 349 through 11 are all N
+    pub fn compass_point(&self) -> &'static str {
+        const POINTS: [&str; 16] = ["N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE",
+                                    "S", "SSW", "SW", "WSW", "W", "WNW", "NW", "NNW"];
+        // in quarter degrees to keep it in integers; + 45 rounds to the nearest point
+        let index = (u32::from(self.gps_hdg % 360) * 4 + 45) / 90 % 16;
+        POINTS[index as usize]
+    }
+
+    pub fn format_lat(&self, format: CoordinateFormat) -> String {
+        format_lat(self.gps_lat.into(), format)
+    }
+
+    pub fn format_long(&self, format: CoordinateFormat) -> String {
+        format_long(self.gps_long.into(), format)
+    }
+}
+
+// how positions are shown to the operator. everything written for machines
+// (the CSV log, JSON, GPX) stays in decimal degrees regardless.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CoordinateFormat {
+    #[default]
+    Decimal,  // 37.42000
+    Dms,      // 37°25'12.0"N
+}
+
+pub fn format_lat(degrees: f64, format: CoordinateFormat) -> String {
+    format_coordinate(degrees, format, 'N', 'S')
+}
+
+pub fn format_long(degrees: f64, format: CoordinateFormat) -> String {
+    format_coordinate(degrees, format, 'E', 'W')
+}
+
+fn format_coordinate(degrees: f64, format: CoordinateFormat, positive: char, negative: char) -> String {
+    match format {
+        CoordinateFormat::Decimal => format!("{:.5}", degrees),
+        CoordinateFormat::Dms => {
+            let hemisphere = if degrees < 0.0 { negative } else { positive };
+            // round to tenths of a second before splitting it up, so 59.96"
+            // carries into the minutes instead of showing as 60.0"
+            let tenths = (degrees.abs() * 36_000.0).round() as u64;
+            format!("{}°{:02}'{:02}.{}\"{}", tenths / 36_000, tenths / 600 % 60, tenths / 10 % 60, tenths % 10, hemisphere)
+        }
+    }
+}
+
+// constructors for the messages the station sends, stamped with the current
+// time, and empty ones for receive to fill in
+impl RoverMessage {
+    pub fn telemetry_ack(ack: bool, command_waiting: bool) -> RoverMessage {
+        RoverMessage::TelemetryAck { timestamp: RoverTimestamp::now(), ack, command_waiting }
+    }
+
+    pub fn command(sequence_complete: bool, command: &str) -> RoverMessage {
+        RoverMessage::CommandMessage { timestamp: RoverTimestamp::now(), sequence_complete, command: command.into() }
+    }
+
+    pub fn request_telemetry() -> RoverMessage {
+        RoverMessage::RequestTelemetry { timestamp: RoverTimestamp::now() }
+    }
+
+    pub fn ping() -> RoverMessage {
+        RoverMessage::Ping { timestamp: RoverTimestamp::now() }
+    }
+
+    pub fn empty_telemetry() -> RoverMessage {
+        RoverMessage::TelemetryMessage { timestamp: Default::default(),
+                                         location: Default::default(),
+                                         signal_strength: 0,
+                                         free_memory: 0,
+                                         battery_millivolts: 0,
+                                         status: String::new() }
+    }
+
+    pub fn empty_command_ready() -> RoverMessage {
+        RoverMessage::CommandReady { timestamp: Default::default(), ready: false }
+    }
+
+    // an empty message of the kind with this message id, for deserialize to fill in
+    fn empty(message_id: u8) -> Option<RoverMessage> {
+        let timestamp = Default::default();
+        Some(match message_id {
+            MESSAGE_TELEMETRY => RoverMessage::empty_telemetry(),
+            MESSAGE_TELEMETRY_ACK => RoverMessage::TelemetryAck { timestamp, ack: false, command_waiting: false },
+            MESSAGE_COMMAND_READY => RoverMessage::empty_command_ready(),
+            MESSAGE_COMMAND => RoverMessage::CommandMessage { timestamp, sequence_complete: false, command: String::new() },
+            MESSAGE_COMMAND_ACK => RoverMessage::CommandAck { timestamp, ack: false },
+            MESSAGE_REQUEST_TELEMETRY => RoverMessage::RequestTelemetry { timestamp },
+            MESSAGE_PING => RoverMessage::Ping { timestamp },
+            MESSAGE_PONG => RoverMessage::Pong { timestamp },
+            _ => return None
+        })
+    }
+}
 
 impl RoverMessage {
     fn get_message_id(&self) -> u8 {
@@ -119,36 +366,52 @@ impl RoverMessage {
             RoverMessage::TelemetryAck { .. } => MESSAGE_TELEMETRY_ACK,
             RoverMessage::CommandReady { .. } => MESSAGE_COMMAND_READY,
             RoverMessage::CommandMessage { .. } => MESSAGE_COMMAND,
-            RoverMessage::CommandAck { .. } => MESSAGE_COMMAND_ACK
+            RoverMessage::CommandAck { .. } => MESSAGE_COMMAND_ACK,
+            RoverMessage::RequestTelemetry { .. } => MESSAGE_REQUEST_TELEMETRY,
+            RoverMessage::Ping { .. } => MESSAGE_PING,
+            RoverMessage::Pong { .. } => MESSAGE_PONG
         }
     }
 
-    fn get_message_type(id: u8) -> &'static str {
+    pub fn get_message_type(id: u8) -> &'static str {
         match id {
             MESSAGE_TELEMETRY => "MESSAGE_TELEMETRY",
             MESSAGE_TELEMETRY_ACK => "MESSAGE_TELEMETRY_ACK",
             MESSAGE_COMMAND_READY => "MESSAGE_COMMAND_READY",
             MESSAGE_COMMAND => "MESSAGE_COMMAND",
             MESSAGE_COMMAND_ACK => "MESSAGE_COMMAND_ACK",
-            _ => "MESSAGE_UNKNOWN"
+            MESSAGE_REQUEST_TELEMETRY => "MESSAGE_REQUEST_TELEMETRY",
+            MESSAGE_PING => "MESSAGE_PING",
+            MESSAGE_PONG => "MESSAGE_PONG",
+            _ => UNKNOWN_MESSAGE
         }
     }
 
-    // this assumes the string is ASCII. if you give it a UTF-8 string that uses multibyte
-    // characters or codes above 127, the rover end will be very confused.
-    // TODO: check for that? somehow?
-    fn serialize_string(s: &String, buf: &mut Vec<u8>) {
+    // the rover only understands ASCII. a UTF-8 string that uses multibyte characters
+    // or codes above 127 (a stray smart quote, say) would leave it very confused, so
+    // refuse to send one.
+    fn serialize_string(s: &String, buf: &mut Vec<u8>) -> Result<()> {
+        if let Some((i, c)) = s.chars().enumerate().find(|(_, c)| !c.is_ascii()) {
+            return Err(ErrorKind::SerializationError(format!("non-ASCII character '{}' at position {} in \"{}\"", c, i, s)).into());
+        }
         for byte in s.as_bytes().iter() {
             buf.push(*byte);
         }
         buf.push(0);
+        Ok(())
     }
 
-    fn deserialize_string(s: &mut String, buf: &mut &[u8]) {
-        for byte in buf.iter() {
+    // the rover only ever sends ASCII, so anything above 127 means the packet
+    // got mangled somewhere along the way (most likely a bad decrypt).
+    fn deserialize_string(s: &mut String, buf: &mut &[u8]) -> Result<()> {
+        for (i, byte) in buf.iter().enumerate() {
             if *byte == 0 { break; }
-            s.push(char::from_u32(*byte as u32).expect("deserialize_string: invalid character skipped"));
+            match char::from_u32(*byte as u32) {
+                Some(c) if c.is_ascii() => s.push(c),
+                _ => return Err(ErrorKind::ReceiveError(format!("invalid character 0x{:02x} at string position {}", byte, i)).into())
+            }
         }
+        Ok(())
     }
 
     fn serialize_bool(b: bool, buf: &mut Vec<u8>) {
@@ -159,8 +422,15 @@ impl RoverMessage {
         }
     }
 
-    fn deserialize_bool(byte: u8) -> bool {
-        byte > 0
+    // a garbled flag could pass for an ack, so with strict_bools only 0 and 1
+    // are taken
+    fn deserialize_bool(strict_bools: bool, byte: u8) -> Result<bool> {
+        match byte {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ if strict_bools => Err(ErrorKind::ReceiveError(format!("flag byte is 0x{:02x}, not 0 or 1", byte)).into()),
+            _ => Ok(true)
+        }
     }
 
     fn serialize_u16(i: &u16, buf: &mut Vec<u8>) {
@@ -183,173 +453,1530 @@ impl RoverMessage {
         i16::from_le_bytes([buf[0], buf[1]])
     }
 
+    // how much of buf the received packet occupies, length byte included
+    fn packet_len(buf: &[u8]) -> usize {
+        RoverMessage::claimed_len(buf).min(buf.len())
+    }
+
+    // what the length byte says the whole packet is, length byte included
+    fn claimed_len(buf: &[u8]) -> usize {
+        buf.first().map_or(0, |len| *len as usize + 1)
+    }
+
+    // CRC-8 with polynomial 0x07 and a zero initial value (CRC-8/SMBUS). the
+    // radio's own CRC catches bit errors on the air; this catches a packet that
+    // arrived intact but that we'd otherwise misparse, e.g. after a bad decrypt.
+    fn crc8(bytes: &[u8]) -> u8 {
+        bytes.iter().fold(0, |crc, byte| {
+            (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 })
+        })
+    }
+
+    // make sure the received packet is long enough to hold the first `needed`
+    // bytes of buf, not counting the checksum on the end. the length byte at
+    // buf[0] doesn't count itself, so the packet actually occupies
+    // buf[0..=buf[0]]. anything past that is left over from a previous receive
+    // and must not be read.
+    fn check_len(buf: &[u8], needed: usize) -> Result<()> {
+        let len = match buf.first() {
+            Some(len) => (*len as usize + 1).saturating_sub(CHECKSUM_SIZE),
+            None => 0
+        };
+        if len < needed || buf.len() < needed {
+            return Err(ErrorKind::ReceiveError(format!("packet too short: expected at least {} bytes, got {}", needed, len)).into());
+        }
+        Ok(())
+    }
+
+    // the tests mostly don't care what's in the header
+    #[cfg(test)]
     fn serialize(&self, buf: &mut Vec<u8>) -> Result<()> {
+        self.serialize_packet(buf, &Default::default())
+    }
+
+    // serialize with the given RadioHead header
+    fn serialize_packet(&self, buf: &mut Vec<u8>, header: &RadioHeadHeader) -> Result<()> {
         // first byte is buffer length - we'll add that at the end
         // next four bytes are used by RadioHead as TO, FROM, ID, FLAGS
         // so push those onto the Vec before serializing the rest of the payload
-        // TODO: if we ever need to put real values for these, do it here
-        buf.push(0xff); // TO
-        buf.push(0xff); // FROM
-        buf.push(0x00); // ID
-        buf.push(0x00); // FLAGS
+        header.serialize(buf);
+        // the station never sends TelemetryMessage, CommandReady, CommandAck or Pong, but
+        // serializing them lets the protocol be exercised without a rover.
         match self {
-            RoverMessage::TelemetryMessage { .. } => { return Err("Station cannot serialize TelemetryMessage".into()) } // never sent by station
+            RoverMessage::TelemetryMessage { timestamp, location, signal_strength, free_memory, battery_millivolts, status } => {
+                buf.push(self.get_message_id());
+                buf.push(PROTOCOL_VERSION);
+                timestamp.serialize(buf);
+                location.serialize(buf);
+                RoverMessage::serialize_i16(signal_strength, buf);
+                RoverMessage::serialize_u16(free_memory, buf);
+                RoverMessage::serialize_u16(battery_millivolts, buf);
+                RoverMessage::serialize_string(status, buf)?;
+            }
             RoverMessage::TelemetryAck { timestamp, ack, command_waiting } => {
                 buf.push(self.get_message_id());
+                buf.push(PROTOCOL_VERSION);
                 timestamp.serialize(buf);
                 RoverMessage::serialize_bool(*ack, buf);
                 RoverMessage::serialize_bool(*command_waiting, buf);
             }
-            RoverMessage::CommandReady { .. } => { return Err("Station cannot serialize CommandReady".into()) } // never sent by station
+            RoverMessage::CommandReady { timestamp, ready } => {
+                buf.push(self.get_message_id());
+                buf.push(PROTOCOL_VERSION);
+                timestamp.serialize(buf);
+                RoverMessage::serialize_bool(*ready, buf);
+            }
             RoverMessage::CommandMessage { timestamp, sequence_complete, command } => {
                 buf.push(self.get_message_id());
+                buf.push(PROTOCOL_VERSION);
                 timestamp.serialize(buf);
                 RoverMessage::serialize_bool(*sequence_complete, buf);
-                RoverMessage::serialize_string(command, buf);
+                RoverMessage::serialize_string(command, buf)?;
+            }
+            RoverMessage::CommandAck { timestamp, ack } => {
+                buf.push(self.get_message_id());
+                buf.push(PROTOCOL_VERSION);
+                timestamp.serialize(buf);
+                RoverMessage::serialize_bool(*ack, buf);
+            }
+            RoverMessage::RequestTelemetry { timestamp } |
+            RoverMessage::Ping { timestamp } |
+            RoverMessage::Pong { timestamp } => {
+                buf.push(self.get_message_id());
+                buf.push(PROTOCOL_VERSION);
+                timestamp.serialize(buf);
             }
-            RoverMessage::CommandAck { .. } => { return Err("Station cannot serialize CommandAck".into()) } // never sent by station
         }
-        // push the length byte onto the *front* of the buffer
-        buf.insert(0, buf.len() as u8);
+        // checksum everything so far, then push the length byte onto the *front* of the buffer.
+        // it counts everything after itself, and has to fit in the one byte.
+        buf.push(RoverMessage::crc8(buf));
+        let length = u8::try_from(buf.len())
+            .map_err(|_| ErrorKind::SerializationError(format!("message is {} bytes, too long for its length byte", buf.len() + 1)))?;
+        buf.insert(0, length);
         Ok(())
     }
 
-    fn deserialize(&mut self, buf: &mut [u8; 64]) -> Result<()> {
+    // fill in this message from a received packet, exactly the bytes that were
+    // received, returning the RadioHead header it came with
+    pub fn deserialize(&mut self, buf: &[u8]) -> Result<RadioHeadHeader> {
+        self.deserialize_with(buf, false)
+    }
+
+    // the same, refusing flags other than 0 and 1 if strict_bools is set
+    pub fn deserialize_with(&mut self, buf: &[u8], strict_bools: bool) -> Result<RadioHeadHeader> {
         // first byte is a length
         // next four bytes are used by RadioHead as TO, FROM, ID, FLAGS
         // so strip those off before deserializing the rest of the payload
-        // TODO: if those values are ever needed, grab them here
+        RoverMessage::check_frame(buf)?;
+        let checksum_at = buf[0] as usize;
+        // everything after the timestamp, up to the checksum; offsets below are
+        // relative to this. check_len makes sure each message's fields are in it.
+        let body = buf.get(BODY_START..checksum_at).unwrap_or_default();
         match self {
             RoverMessage::TelemetryMessage { ref mut timestamp,
                                              ref mut location,
                                              signal_strength,
                                              free_memory,
+                                             battery_millivolts,
                                              ref mut status } => {
                 if buf[5] != MESSAGE_TELEMETRY {
                     return Err(format!("Wrong message type: expected MESSAGE_TELEMETRY, got {}", RoverMessage::get_message_type(buf[5])).into());
                 }
-                timestamp.deserialize(&mut &buf[6..12]);
-                location.deserialize(&mut &buf[12..31]);
-                *signal_strength = RoverMessage::deserialize_i16(&mut &buf[31..33]);
-                *free_memory = RoverMessage::deserialize_u16(&mut &buf[33..35]);
-                RoverMessage::deserialize_string(status, &mut &buf[35..]);
+                RoverMessage::check_len(buf, BODY_START + LOC_DATA_SIZE + 7)?; // status needs at least its terminator
+                timestamp.deserialize(&mut &buf[HEADER_SIZE..BODY_START]);
+                location.deserialize(&mut &body[..LOC_DATA_SIZE]);
+                location.validate()?;
+                let body = &body[LOC_DATA_SIZE..];
+                *signal_strength = RoverMessage::deserialize_i16(&mut &body[0..2]);
+                *free_memory = RoverMessage::deserialize_u16(&mut &body[2..4]);
+                *battery_millivolts = RoverMessage::deserialize_u16(&mut &body[4..6]);
+                RoverMessage::deserialize_string(status, &mut &body[6..])?;
+            }
+            RoverMessage::TelemetryAck { ref mut timestamp, ref mut ack, ref mut command_waiting } => {
+                if buf[5] != MESSAGE_TELEMETRY_ACK {
+                    return Err(format!("Wrong message type: expected MESSAGE_TELEMETRY_ACK, got {}", RoverMessage::get_message_type(buf[5])).into());
+                }
+                RoverMessage::check_len(buf, BODY_START + 2)?;
+                timestamp.deserialize(&mut &buf[HEADER_SIZE..BODY_START]);
+                *ack = RoverMessage::deserialize_bool(strict_bools, body[0])?;
+                *command_waiting = RoverMessage::deserialize_bool(strict_bools, body[1])?;
             }
-            RoverMessage::TelemetryAck { .. } => { return Err("Station cannot deserialize TelemetryAck".into()); }
             RoverMessage::CommandReady { ref mut timestamp, ref mut ready } => {
                 if buf[5] != MESSAGE_COMMAND_READY {
                     return Err(format!("Wrong message type: expected MESSAGE_COMMAND_READY, got {}", RoverMessage::get_message_type(buf[5])).into());
                 }
-                timestamp.deserialize(&mut &buf[6..12]);
-                *ready = RoverMessage::deserialize_bool(buf[12]);
+                RoverMessage::check_len(buf, BODY_START + 1)?;
+                timestamp.deserialize(&mut &buf[HEADER_SIZE..BODY_START]);
+                *ready = RoverMessage::deserialize_bool(strict_bools, body[0])?;
+            }
+            RoverMessage::CommandMessage { ref mut timestamp, ref mut sequence_complete, ref mut command } => {
+                if buf[5] != MESSAGE_COMMAND {
+                    return Err(format!("Wrong message type: expected MESSAGE_COMMAND, got {}", RoverMessage::get_message_type(buf[5])).into());
+                }
+                RoverMessage::check_len(buf, BODY_START + 2)?; // command needs at least its terminator
+                timestamp.deserialize(&mut &buf[HEADER_SIZE..BODY_START]);
+                *sequence_complete = RoverMessage::deserialize_bool(strict_bools, body[0])?;
+                RoverMessage::deserialize_string(command, &mut &body[1..])?;
             }
-            RoverMessage::CommandMessage { .. } => { return Err("Station cannot deserialize CommandMessage".into()); }
             RoverMessage::CommandAck { ref mut timestamp, ref mut ack } => {
                 if buf[5] != MESSAGE_COMMAND_ACK {
                     return Err(format!("Wrong message type: expected MESSAGE_COMMAND_ACK, got {}", RoverMessage::get_message_type(buf[5])).into());
                 }
-                timestamp.deserialize(&mut &buf[6..12]);
-                *ack = RoverMessage::deserialize_bool(buf[12]);
+                RoverMessage::check_len(buf, BODY_START + 1)?;
+                timestamp.deserialize(&mut &buf[HEADER_SIZE..BODY_START]);
+                *ack = RoverMessage::deserialize_bool(strict_bools, body[0])?;
+            }
+            RoverMessage::RequestTelemetry { ref mut timestamp } => {
+                if buf[5] != MESSAGE_REQUEST_TELEMETRY {
+                    return Err(format!("Wrong message type: expected MESSAGE_REQUEST_TELEMETRY, got {}", RoverMessage::get_message_type(buf[5])).into());
+                }
+                RoverMessage::check_len(buf, BODY_START)?;
+                timestamp.deserialize(&mut &buf[HEADER_SIZE..BODY_START]);
+            }
+            RoverMessage::Ping { ref mut timestamp } => {
+                if buf[5] != MESSAGE_PING {
+                    return Err(format!("Wrong message type: expected MESSAGE_PING, got {}", RoverMessage::get_message_type(buf[5])).into());
+                }
+                RoverMessage::check_len(buf, BODY_START)?;
+                timestamp.deserialize(&mut &buf[HEADER_SIZE..BODY_START]);
+            }
+            RoverMessage::Pong { ref mut timestamp } => {
+                if buf[5] != MESSAGE_PONG {
+                    return Err(format!("Wrong message type: expected MESSAGE_PONG, got {}", RoverMessage::get_message_type(buf[5])).into());
+                }
+                RoverMessage::check_len(buf, BODY_START)?;
+                timestamp.deserialize(&mut &buf[HEADER_SIZE..BODY_START]);
             }
         }
+        let mut header: RadioHeadHeader = Default::default();
+        header.deserialize(&mut &buf[1..]);
+        Ok(header)
+    }
+
+    // the checks every frame has to pass before anything in it is believed:
+    // it's all there, the checksum matches and it's our protocol version
+    fn check_frame(buf: &[u8]) -> Result<()> {
+        RoverMessage::check_len(buf, HEADER_SIZE)?;
+        let checksum_at = buf[0] as usize;
+        if checksum_at >= buf.len() {
+            return Err(ErrorKind::ReceiveError(format!("length byte {} runs past the {} bytes received", buf[0], buf.len())).into());
+        }
+        let checksum = RoverMessage::crc8(&buf[1..checksum_at]);
+        if checksum != buf[checksum_at] {
+            return Err(ErrorKind::ReceiveError(format!("checksum mismatch: computed 0x{:02x}, packet has 0x{:02x}",
+                                                       checksum, buf[checksum_at])).into());
+        }
+        if buf[6] != PROTOCOL_VERSION {
+            return Err(ErrorKind::ReceiveError(format!("protocol version mismatch: expected {}, got {}", PROTOCOL_VERSION, buf[6])).into());
+        }
+        Ok(())
+    }
+
+    // make sure this message's string field (if it has one) fits in a packet,
+    // leaving room for the fixed fields and the string's terminator. the radio
+    // would otherwise truncate it and the rover would get half a command.
+    fn check_string_length(&self, config: &RadioConfig) -> Result<()> {
+        let (field, s, fixed_length) = match self {
+            RoverMessage::TelemetryMessage { status, .. } => ("status", status, BODY_START + LOC_DATA_SIZE + 6),
+            RoverMessage::CommandMessage { command, .. } => ("command", command, BODY_START + 1),
+            _ => return Ok(())
+        };
+        let max_length = RoverMessage::max_string_length(config, fixed_length);
+        if s.len() > max_length {
+            return Err(ErrorKind::SerializationError(format!("{} is {} chars, but the limit is {} with encryption {}",
+                                                    field, s.len(), max_length,
+                                                    if config.use_encryption { "on" } else { "off" })).into());
+        }
         Ok(())
     }
 
-    // send msg via radio rfm; wait up to ack_timeout milliseconds for an
-    // acknowledgement if needed. ACK logic is encapsulated here - e.g.,
-    // a CommandMessage expects an ACK, but a TelemetryAck does not.
-    pub fn send(&self,
-            rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>) -> Result<()> {
-        let mut max_message_length = 255;
-        if USE_ENCRYPTION { max_message_length = 64; }
+    // room left for a string in a packet with fixed_length bytes of other fields
+    fn max_string_length(config: &RadioConfig, fixed_length: usize) -> usize {
+        config.max_message_length() - fixed_length - 1 - CHECKSUM_SIZE  // 1 for the string's terminator
+    }
+
+    // an empty instance of the message the rover answers this one with, if any
+    fn expected_reply(&self) -> Option<RoverMessage> {
+        match self {
+            RoverMessage::CommandMessage { .. } => Some(RoverMessage::CommandAck { timestamp: Default::default(),
+                                                                                   ack: false }),
+            RoverMessage::RequestTelemetry { .. } => Some(RoverMessage::empty_telemetry()),
+            RoverMessage::Ping { .. } => Some(RoverMessage::Pong { timestamp: Default::default() }),
+            _ => None
+        }
+    }
+
+    // send msg via radio rfm; wait up to config.timing.ack_timeout_ms for an
+    // acknowledgement if needed, and return it. ACK logic is encapsulated
+    // here - e.g., a CommandMessage expects an ACK, but a TelemetryAck does
+    // not, and a RequestTelemetry is answered with a TelemetryMessage.
+    // messages that expect a reply are retransmitted up to
+    // config.timing.send_attempts times, backing off a little longer before
+    // each retry.
+    // a command too long for one packet is split into fragments.
+    pub fn send<R: RadioLink>(&self, rfm: &mut R, config: &RadioConfig,
+                              stats: &mut LinkStats) -> Result<Option<RoverMessage>> {
+        if let RoverMessage::CommandMessage { timestamp, sequence_complete, command } = self {
+            let max_length = RoverMessage::max_string_length(config, BODY_START + 1);
+            if command.len() > max_length {
+                return RoverMessage::send_fragments(timestamp, *sequence_complete, command, max_length,
+                                                    rfm, config, stats);
+            }
+        }
+        self.send_packet(0, rfm, config, stats)
+    }
+
+    // send command as a series of CommandMessages of at most max_length
+    // characters each, waiting for the rover to ack each one before moving on.
+    // sequence_complete goes on the last one only.
+    fn send_fragments<R: RadioLink>(timestamp: &RoverTimestamp, sequence_complete: bool, command: &str,
+                                    max_length: usize, rfm: &mut R, config: &RadioConfig,
+                                    stats: &mut LinkStats) -> Result<Option<RoverMessage>> {
+        // ASCII only, so any byte is a safe place to split
+        if !command.is_ascii() {
+            return Err(ErrorKind::SerializationError(format!("cannot serialize non-ASCII string: {:?}", command)).into());
+        }
+        let fragments: Vec<&[u8]> = command.as_bytes().chunks(max_length).collect();
+        if fragments.len() > MAX_FRAGMENTS {
+            return Err(ErrorKind::SerializationError(format!("command is {} chars, but the limit is {} with encryption {}",
+                                                    command.len(), max_length * MAX_FRAGMENTS,
+                                                    if config.use_encryption { "on" } else { "off" })).into());
+        }
+        let mut reply = None;
+        for (index, fragment) in fragments.iter().enumerate() {
+            let last = index == fragments.len() - 1;
+            let msg = RoverMessage::CommandMessage { timestamp: timestamp.clone(),
+                                                     sequence_complete: sequence_complete && last,
+                                                     command: String::from_utf8_lossy(fragment).into_owned() };
+            let flags = (index as u8 & FRAGMENT_INDEX_MASK) | if last { 0 } else { FLAG_MORE_FRAGMENTS };
+            reply = msg.send_packet(flags, rfm, config, stats)
+                .chain_err(|| ErrorKind::SendError(format!("sending command fragment {} of {}", index + 1, fragments.len())))?;
+        }
+        Ok(reply)
+    }
+
+    // send a message that fits in one packet, with the given FLAGS byte
+    fn send_packet<R: RadioLink>(&self, flags: u8, rfm: &mut R, config: &RadioConfig,
+                                 stats: &mut LinkStats) -> Result<Option<RoverMessage>> {
+        let max_message_length = config.max_message_length();
+        self.check_string_length(config)?;
         // serialize the message
+        // retransmissions keep the same sequence number so the rover can spot them
+        let header = RadioHeadHeader { to: config.send_to()?,
+                                       from: config.station_node_id,
+                                       id: stats.sequences.next(self.get_message_id()),
+                                       flags };
         let mut buf = Vec::new();
-        RoverMessage::serialize(&self, &mut buf).unwrap();
+        // a TelemetryAck is the rover's time signal, so its timestamp is read
+        // now, not whenever the ack happened to be made up
+        match self {
+            RoverMessage::TelemetryAck { ack, command_waiting, .. } =>
+                RoverMessage::TelemetryAck { timestamp: RoverTimestamp::now(), ack: *ack, command_waiting: *command_waiting }
+                    .serialize_packet(&mut buf, &header)?,
+            _ => self.serialize_packet(&mut buf, &header)?
+        }
         // check message length
         if buf.len() > max_message_length {
-            return Err(format!("Cannot send: message too long! {:?}", self).into())
-        }
-        // send it
-        // DEBUG
-        // println!("DEBUG: sending this message:");
-        // for byte in buf.iter() {
-        //     print!("{:x} ", byte);
-        // }
-        // println!();
-        match rfm.send(buf.as_slice()) {
-        //match RoverMessage::debug_send(rfm, buf.as_slice()) {
-            Err(e) => return Err(format!("Error while sending message: {:?}", e).into()),
-            _ => {}
-        }
-        // receive ack if appropriate
-        match self {
-            RoverMessage::CommandMessage { .. } => {
-                let mut ack: RoverMessage = RoverMessage::CommandAck { timestamp: Default::default(),
-                                                                   ack: false };
-                ack.receive(rfm, ACK_TIMEOUT)?
-            },
-            _ => (), // no ack needed
+            return Err(ErrorKind::SerializationError(format!("message is {} bytes, but the limit is {}: {}",
+                                                             buf.len(), max_message_length, self)).into());
         }
-        Ok(())
+        let attempts = match self.expected_reply() {
+            Some(_) => config.timing.send_attempts.max(1),  // 0 would never send at all
+            None => 1
+        };
+        let mut last_error: Error = ErrorKind::SendError("message was never sent".into()).into();
+        for attempt in 1..=attempts {
+            if attempt > 1 {
+                thread::sleep(Duration::from_millis(RETRY_DELAY * (attempt - 1)));
+            }
+            // send it
+            if let Some(lbt) = &config.lbt {
+                wait_for_clear_channel(rfm, lbt)?;
+            }
+            dump_frame("sending", &buf);
+            rfm.send(buf.as_slice()).chain_err(|| "Error while sending message")?;
+            // receive ack if appropriate
+            let mut reply = match self.expected_reply() {
+                Some(reply) => reply,
+                None => return Ok(None) // no ack needed
+            };
+            match reply.receive(rfm, config, stats, config.timing.ack_timeout_ms) {
+                Ok(_) => match reply {
+                    RoverMessage::CommandAck { ack: false, .. }
+                        => last_error = ErrorKind::SendError("rover refused the command".into()).into(),
+                    _ => return Ok(Some(reply))
+                },
+                Err(e) => last_error = e
+            }
+        }
+        Err(last_error).chain_err(|| ErrorKind::SendError(format!("no acknowledgement after {} attempts", attempts)))
     }
 
     // receive the next message via radio rfm, ack if necessary, and return
     // the received message. ACK logic is encapsulated here - e.g., a
     // TelemetryMessage should be ACKed but a CommandAck message should not.
-    // this gets slightly awkward if the rover responds with an inappropriate
-    // message (e.g., station sends CommandMessage, then rover sends TelemetryMessage
-    // instead of CommandAck - station will still ACK the TelemetryMessage before
-    // bubbling back and reporting the error).
-    pub fn receive(&mut self,
-               rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>,
-               timeout: u64) -> Result<()> {
+    // if the rover responds with an inappropriate message (e.g., station sends
+    // CommandMessage, then rover sends TelemetryMessage instead of CommandAck),
+    // the kind is checked first and the error reported without ACKing it, so
+    // the rover sends it again later rather than thinking it was delivered.
+    // a ReceiveError means nothing usable arrived (timeout or a bad packet) and
+    // it's reasonable to keep listening; a RadioError means the radio itself
+    // is in trouble.
+    // the signal strength of everything received is recorded in stats. the
+    // RadioHead header the message came with is returned, e.g. to see who sent it.
+    pub fn receive<R: RadioLink>(&mut self, rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats,
+                                 timeout: u64) -> Result<RadioHeadHeader> {
+        self.receive_with_ack(rfm, config, stats, timeout, false)
+    }
+
+    // like receive, but a TelemetryMessage is acked with command_waiting set
+    // as given. with it set, the rover follows up with a CommandReady.
+    // a message of some other kind isn't acked: it's being thrown away, so
+    // the rover had better send it again.
+    pub fn receive_with_ack<R: RadioLink>(&mut self, rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats,
+                                          timeout: u64, command_waiting: bool) -> Result<RadioHeadHeader> {
+        let (message, header, duplicate) = RoverMessage::receive_unacked(rfm, config, stats, timeout)?;
+        if message.get_message_id() != self.get_message_id() {
+            return Err(ErrorKind::ReceiveError(format!("expected {}, got {}",
+                                                       RoverMessage::get_message_type(self.get_message_id()),
+                                                       RoverMessage::get_message_type(message.get_message_id()))).into());
+        }
+        message.ack(rfm, config, stats, command_waiting)?;
+        RoverMessage::check_duplicate(duplicate, &header, message.get_message_id())?;
+        *self = message;
+        Ok(header)
+    }
+
+    // receive whatever the rover sends next, whichever kind of message it is,
+    // acking it if it needs one (see receive_with_ack for command_waiting).
+    // the RadioHead header it came with is returned along with it.
+    pub fn receive_any<R: RadioLink>(rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats,
+                                     timeout: u64, command_waiting: bool) -> Result<(RoverMessage, RadioHeadHeader)> {
+        let (message, header, duplicate) = RoverMessage::receive_unacked(rfm, config, stats, timeout)?;
+        message.ack(rfm, config, stats, command_waiting)?;
+        RoverMessage::check_duplicate(duplicate, &header, message.get_message_id())?;
+        Ok((message, header))
+    }
+
+    // ACK if necessary - duplicates too, since the rover is retransmitting
+    // because it never heard the first ack. only ever called with a message
+    // that's passed its checksum and deserialized, and is the kind expected.
+    fn ack<R: RadioLink>(&self, rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats, command_waiting: bool) -> Result<()> {
+        match self {
+            RoverMessage::TelemetryMessage{..} => {
+                let ack = RoverMessage::telemetry_ack(true, command_waiting);
+                thread::sleep(Duration::from_millis(config.timing.msg_delay_ms));
+                ack.send(rfm, config, stats)?;
+            },
+            _ => () // no ack needed
+        }
+        Ok(())
+    }
+
+    // a duplicate is acked like any other, but then goes no further
+    fn check_duplicate(duplicate: bool, header: &RadioHeadHeader, message_id: u8) -> Result<()> {
+        if duplicate {
+            return Err(ErrorKind::ReceiveError(format!("discarding duplicate {} (sequence {})",
+                                                       RoverMessage::get_message_type(message_id), header.id)).into());
+        }
+        Ok(())
+    }
+
+    // the next good message from the rover, with its header and whether it's
+    // a retransmission of the last of its kind, without acking it. anything
+    // that fails its checksum or won't deserialize is a ReceiveError.
+    fn receive_unacked<R: RadioLink>(rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats,
+                                     timeout: u64) -> Result<(RoverMessage, RadioHeadHeader, bool)> {
         let mut buf = [0 as u8; 64];
-        // recv claims it "blocks until there are any bytes available"
-        // but this is a lie; it actually has a hardcoded timeout of 100ms
-        // and returns a timeout error if there are no packets in that time.
+        let mut len = 0;
         let start = Instant::now();
         let mut complete = false;
         while !complete {
-            match rfm.recv(&mut buf) {
-                Ok(_) => { complete = true; },
-                Err(e) => {
-                    match e {
-                        rfm69::Error::Timeout => {
-                            thread::sleep(Duration::from_millis(LISTEN_DELAY));
-                            // eat timeouts but cough up anything else
-                        },
-                        _ => {
-                            return Err(format!("Error while waiting for RoverMessage: {:?}", e).into())
+            match rfm.recv(&mut buf, listen_wait(&config.timing)) {
+                // nothing arrived this time around, keep listening
+                Ok(0) => (),
+                Ok(received) => {
+                    // clear out whatever an earlier, longer packet left behind
+                    len = received.min(buf.len());
+                    buf[len..].fill(0);
+                    // captured as it came off the radio, before anything can go wrong with it
+                    if let Some(capture) = &mut stats.capture {
+                        if let Err(e) = capture.write(&buf) {
+                            warn!("Error capturing frame: {}", e);
                         }
                     }
-                }
+                    // another rover's packet, or one for another station: ignore it and keep listening
+                    complete = wanted(config, &buf);
+                },
+                // a packet garbled on the air: count it, and keep listening for a good one
+                Err(Error(ErrorKind::Rfm69(Rfm69Error::Crc), _)) => {
+                    stats.record_crc_failure();
+                    debug!("Packet failed its CRC");
+                },
+                Err(e) => return Err(e).chain_err(|| ErrorKind::RadioError("waiting for RoverMessage".into()))
             }
             if Instant::now().duration_since(start) > Duration::from_millis(timeout) { break };
-            thread::sleep(Duration::from_millis(LISTEN_DELAY));
-        }
-        if !complete { return Err("Timed out while waiting for RoverMessage.".into()) }
-        // DEBUG
-        // println!("DEBUG: received this message:");
-        // for byte in buf.iter() {
-        //     print!("{:x} ", byte);
-        // }
-        // println!();
-        println!("Received message from rover; signal strength {}", rfm.rssi());
+        }
+        if !complete { return Err(ErrorKind::ReceiveError("timed out waiting for RoverMessage".into()).into()) }
+        dump_frame("received", &buf[..RoverMessage::packet_len(&buf)]);
+        let rssi = rfm.rssi();
+        stats.record_rssi(rssi);
+        debug!("Received message from rover; signal strength {} dBm", rssi);
+        // a length byte garbled (by a bad decrypt, say) can claim more than
+        // the radio could ever have delivered; don't go looking for the rest
+        let claimed = RoverMessage::claimed_len(&buf);
+        if claimed > len {
+            stats.messages.record_malformed();
+        }
+        if claimed > buf.len() {
+            return Err(ErrorKind::ReceiveError(format!("packet claims {} bytes, more than the {} byte buffer", claimed, buf.len())).into());
+        }
+        if claimed > len {
+            return Err(ErrorKind::ReceiveError(format!("packet claims {} bytes, but only {} were received", claimed, len)).into());
+        }
+        // work out what it is from its message id, once the checksum says the id can be believed
+        let buf = &buf[..len];
+        if let Err(e) = RoverMessage::check_frame(buf) {
+            stats.messages.record_malformed();
+            return Err(e).chain_err(|| ErrorKind::ReceiveError("deserializing response".into()));
+        }
+        let mut message = match RoverMessage::empty(buf[MESSAGE_ID_OFFSET]) {
+            Some(message) => message,
+            None => {
+                stats.messages.record_unknown();
+                return Err(ErrorKind::ReceiveError(format!("unknown message id {}", buf[MESSAGE_ID_OFFSET])).into());
+            }
+        };
         // deserialize the message
-        match self.deserialize(&mut buf) {
-            Err(e) => return Err(format!("Error while deserializing response: {:?}", e).into()),
-            _ => {}
+        let header = match message.deserialize_with(buf, config.strict_bools) {
+            Ok(header) => header,
+            Err(e) => {
+                stats.messages.record_malformed();
+                return Err(e).chain_err(|| ErrorKind::ReceiveError("deserializing response".into()));
+            }
+        };
+        // duplicates included; they did arrive
+        stats.messages.record(buf[MESSAGE_ID_OFFSET]);
+        if let Some(signal_log) = &mut stats.signal_log {
+            if let Err(e) = signal_log.log(&message, rssi) {
+                warn!("Error logging signal strength: {}", e);
+            }
         }
-        // ACK if necessary
-        match self {
-            RoverMessage::TelemetryMessage{..} => {
-                let ack: RoverMessage = RoverMessage::TelemetryAck { timestamp: Default::default(),
-                                                                     ack: true,
-                                                                     command_waiting: false };
-                thread::sleep(Duration::from_millis(MSG_DELAY));
-                ack.send(rfm)?
+        let duplicate = stats.sequences.is_duplicate(buf[MESSAGE_ID_OFFSET], header.id);
+        Ok((message, header, duplicate))
+    }
+}
+
+// a one line summary for the console, easier to follow during a mission than
+// the Debug output. signal strength and satellites come first for telemetry,
+// since they're what an operator watches most.
+impl fmt::Display for RoverMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.summary(CoordinateFormat::Decimal).fmt(f)
+    }
+}
+
+// the one line summary with positions shown the operator's way
+pub struct Summary<'a> {
+    message: &'a RoverMessage,
+    coordinates: CoordinateFormat,
+}
+
+impl RoverMessage {
+    pub fn summary(&self, coordinates: CoordinateFormat) -> Summary<'_> {
+        Summary { message: self, coordinates }
+    }
+}
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.message {
+            RoverMessage::TelemetryMessage { timestamp, location, signal_strength, free_memory, battery_millivolts, status } =>
+                write!(f, "TELEM {} rover_rssi={}dBm sats={} lat={} lon={} alt={:.0}m speed={:.1} hdg={}({}) batt={}mV mem={} status={}",
+                       timestamp, signal_strength, location.gps_sats,
+                       location.format_lat(self.coordinates), location.format_long(self.coordinates),
+                       location.gps_alt, location.gps_speed, location.gps_hdg, location.compass_point(), battery_millivolts, free_memory, status),
+            RoverMessage::TelemetryAck { timestamp, ack, command_waiting } =>
+                write!(f, "TELEM_ACK {} ack={} command_waiting={}", timestamp, ack, command_waiting),
+            RoverMessage::CommandReady { timestamp, ready } =>
+                write!(f, "CMD_READY {} ready={}", timestamp, ready),
+            RoverMessage::CommandMessage { timestamp, sequence_complete, command } =>
+                write!(f, "CMD {} sequence_complete={} command={}", timestamp, sequence_complete, command),
+            RoverMessage::CommandAck { timestamp, ack } =>
+                write!(f, "CMD_ACK {} ack={}", timestamp, ack),
+            RoverMessage::RequestTelemetry { timestamp } =>
+                write!(f, "REQ_TELEM {}", timestamp),
+            RoverMessage::Ping { timestamp } =>
+                write!(f, "PING {}", timestamp),
+            RoverMessage::Pong { timestamp } =>
+                write!(f, "PONG {}", timestamp),
+        }
+    }
+}
+
+// ping the rover and time how long the Pong takes to come back. the time
+// includes any retransmissions, so a lossy link shows up as a slow one.
+pub fn send_ping<R: RadioLink>(rfm: &mut R, config: &RadioConfig, stats: &mut LinkStats) -> Result<Duration> {
+    let ping = RoverMessage::ping();
+    let start = Instant::now();
+    ping.send(rfm, config, stats)?
+        .ok_or_else(|| Error::from(ErrorKind::ReceiveError("no reply to Ping".into())))?;
+    let rtt = start.elapsed();
+    stats.record_rtt(rtt);
+    Ok(rtt)
+}
+
+// log target for frame dumps, so they can be turned on by themselves with
+// RUST_LOG=frames=debug (RUST_LOG=debug turns them on along with everything else)
+const FRAME_LOG_TARGET: &str = "frames";
+const DUMP_BYTES_PER_LINE: usize = 8;
+
+// what kind of message a received frame holds, by its message id, without
+// deserializing it. None if the frame is too short or the id isn't one we know.
+pub fn peek_message_id(buf: &[u8]) -> Option<&'static str> {
+    match RoverMessage::get_message_type(*buf.get(MESSAGE_ID_OFFSET)?) {
+        UNKNOWN_MESSAGE => None,
+        message_type => Some(message_type)
+    }
+}
+
+// listen before talk: wait until nobody else is transmitting, backing off a
+// random amount each time the channel is busy so that we and the rover don't
+// keep picking the same moment. a SendError if it never goes quiet.
+fn wait_for_clear_channel<R: RadioLink>(rfm: &mut R, lbt: &ListenBeforeTalk) -> Result<()> {
+    let mut rssi = 0.0;
+    for _ in 0..lbt.attempts {
+        rssi = rfm.channel_rssi()?;
+        if rssi <= lbt.threshold_dbm {
+            return Ok(());
+        }
+        debug!("Channel busy ({} dBm), backing off", rssi);
+        thread::sleep(Duration::from_millis(random_below(lbt.max_backoff_ms + 1)));
+    }
+    Err(ErrorKind::SendError(format!("channel still busy ({} dBm) after {} attempts", rssi, lbt.attempts)).into())
+}
+
+// whether a frame is from the rover we're listening to (if it's just the one)
+// and for this station (if we only take what's addressed to us). like
+// RadioHead, broadcasts count as addressed to everyone.
+fn wanted(config: &RadioConfig, buf: &[u8]) -> bool {
+    let from_rover = !matches!(config.rover_address, Some(rover) if buf[FROM_OFFSET] != rover);
+    let to_station = !config.addressed_only || buf[TO_OFFSET] == config.station_node_id || buf[TO_OFFSET] == BROADCAST_ADDRESS;
+    from_rover && to_station
+}
+
+// the longest sending a message that expects a reply can take, giving up
+// after every attempt and listening before talking each time, plus acking the
+// reply, for anything that has to allow for it
+pub fn longest_send(config: &RadioConfig) -> Duration {
+    let timing = &config.timing;
+    let attempts = timing.send_attempts.max(1);
+    let backoffs: u64 = (1..attempts).map(|attempt| RETRY_DELAY * attempt).sum();
+    let lbt = config.lbt.map_or(0, |lbt| u64::from(lbt.attempts) * lbt.max_backoff_ms);
+    let attempt = lbt + timing.ack_timeout_ms + timing.listen_delay_ms + timing.listen_jitter_ms;
+    Duration::from_millis(backoffs + attempts * attempt + timing.msg_delay_ms + lbt)
+}
+
+// how long to listen for at a time
+fn listen_wait(timing: &Timing) -> Duration {
+    Duration::from_millis(timing.listen_delay_ms + random_below(timing.listen_jitter_ms + 1))
+}
+
+// the clock's nanoseconds are plenty random enough to spread out backoffs and listens
+fn random_below(n: u64) -> u64 {
+    let nanos = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |t| t.subsec_nanos());
+    nanos as u64 % n.max(1)
+}
+
+// log a raw frame, with the header bytes labeled and the rest as offset-
+// annotated hex. handy when the rover firmware and the station disagree about
+// the wire format.
+pub fn dump_frame(label: &str, buf: &[u8]) {
+    if log_enabled!(target: FRAME_LOG_TARGET, Level::Debug) {
+        debug!(target: FRAME_LOG_TARGET, "{}", format_frame(label, buf));
+    }
+}
+
+fn format_frame(label: &str, buf: &[u8]) -> String {
+    const LABELS: [&str; HEADER_SIZE] = ["length", "TO", "FROM", "ID", "FLAGS", "message id", "protocol version"];
+    let mut out = format!("{} frame, {} bytes:", label, buf.len());
+    // writing to a String can't fail
+    for (offset, (byte, name)) in buf.iter().zip(LABELS.iter()).enumerate() {
+        let _ = write!(out, "\n  {:02}: {:02x}  {}", offset, byte, name);
+        match offset {
+            0 => { let _ = write!(out, " ({})", byte); },
+            MESSAGE_ID_OFFSET => { let _ = write!(out, " ({})", RoverMessage::get_message_type(*byte)); },
+            _ => ()
+        }
+    }
+    if buf.len() > HEADER_SIZE {
+        for (line, chunk) in buf[HEADER_SIZE..].chunks(DUMP_BYTES_PER_LINE).enumerate() {
+            let _ = write!(out, "\n  {:02}:", HEADER_SIZE + line * DUMP_BYTES_PER_LINE);
+            for byte in chunk {
+                let _ = write!(out, " {:02x}", byte);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::radio::SendMode;
+    use std::collections::VecDeque;
+    use std::sync::mpsc::{ self, Receiver, RecvTimeoutError, Sender };
+
+    // feeds canned frames to receive and records everything sent. other
+    // modules' tests use it too.
+    #[derive(Default)]
+    pub(crate) struct MockRadio {
+        incoming: VecDeque<Vec<u8>>,
+        pub(crate) sent: Vec<Vec<u8>>,
+        busy: usize,  // carrier sense readings that will find the channel busy
+        beacon: Option<Beacon>,  // once the canned frames run out
+    }
+
+    // a rover sending the same message on a fixed period, each time with the
+    // next sequence number. the radio only has it for on_air after it's
+    // sent, and time only passes while recv waits, so nothing really sleeps.
+    struct Beacon {
+        msg: RoverMessage,
+        period: Duration,
+        on_air: Duration,
+        now: Duration,
+    }
+
+    impl MockRadio {
+        fn recv_queued(&mut self, buf: &mut [u8]) -> Result<usize> {
+            match self.incoming.pop_front() {
+                Some(frame) => {
+                    buf[..frame.len()].copy_from_slice(&frame);
+                    Ok(frame.len())
+                },
+                None => Ok(0)
+            }
+        }
+
+        fn queue(&mut self, msg: &RoverMessage) {
+            self.queue_sequence(msg, 0);
+        }
+
+        pub(crate) fn queue_sequence(&mut self, msg: &RoverMessage, sequence: u8) {
+            self.queue_header(msg, &RadioHeadHeader { id: sequence, ..Default::default() });
+        }
+
+        fn queue_header(&mut self, msg: &RoverMessage, header: &RadioHeadHeader) {
+            let mut frame = Vec::new();
+            msg.serialize_packet(&mut frame, header).unwrap();
+            self.incoming.push_back(frame);
+        }
+    }
+
+    impl RadioLink for MockRadio {
+        fn send(&mut self, buf: &[u8]) -> Result<()> {
+            self.sent.push(buf.to_vec());
+            Ok(())
+        }
+
+        fn recv(&mut self, buf: &mut [u8], wait: Duration) -> Result<usize> {
+            let beacon = match &mut self.beacon {
+                Some(beacon) if self.incoming.is_empty() => beacon,
+                _ => return self.recv_queued(buf)
+            };
+            beacon.now += wait;
+            let sent = beacon.now.as_millis() / beacon.period.as_millis();
+            if beacon.now.as_millis() % beacon.period.as_millis() < beacon.on_air.as_millis() {
+                let msg = beacon.msg.clone();
+                self.queue_sequence(&msg, sent as u8);
+            }
+            self.recv_queued(buf)
+        }
+
+        fn rssi(&self) -> f32 {
+            -60.0
+        }
+
+        fn channel_rssi(&mut self) -> Result<f32> {
+            if self.busy > 0 {
+                self.busy -= 1;
+                Ok(-50.0)
+            } else {
+                Ok(-100.0)
+            }
+        }
+    }
+
+    // one of a pair of radios that only hear each other: whatever one sends,
+    // the other receives. for tests that need a real conversation, with the
+    // other side running on a thread of its own.
+    pub(crate) struct LoopbackRadio {
+        outgoing: Sender<Vec<u8>>,
+        incoming: Receiver<Vec<u8>>,
+    }
+
+    impl LoopbackRadio {
+        pub(crate) fn pair() -> (LoopbackRadio, LoopbackRadio) {
+            let (to_b, from_a) = mpsc::channel();
+            let (to_a, from_b) = mpsc::channel();
+            (LoopbackRadio { outgoing: to_b, incoming: from_b }, LoopbackRadio { outgoing: to_a, incoming: from_a })
+        }
+    }
+
+    impl RadioLink for LoopbackRadio {
+        fn send(&mut self, buf: &[u8]) -> Result<()> {
+            // once the other end is gone, transmitting is shouting into the void
+            let _ = self.outgoing.send(buf.to_vec());
+            Ok(())
+        }
+
+        fn recv(&mut self, buf: &mut [u8], wait: Duration) -> Result<usize> {
+            match self.incoming.recv_timeout(wait) {
+                Ok(frame) => {
+                    buf[..frame.len()].copy_from_slice(&frame);
+                    Ok(frame.len())
+                },
+                Err(RecvTimeoutError::Timeout) => Ok(0),
+                // and there'll never be anything more to hear
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(wait);
+                    Ok(0)
+                }
+            }
+        }
+
+        fn rssi(&self) -> f32 {
+            -40.0
+        }
+
+        fn channel_rssi(&mut self) -> Result<f32> {
+            Ok(-100.0)
+        }
+    }
+
+    // a rover that hears one transmission for each entry in answers, acking
+    // the ones that are true, and hands back everything it heard
+    fn acking_rover(mut radio: LoopbackRadio, answers: Vec<bool>) -> thread::JoinHandle<Vec<Vec<u8>>> {
+        thread::spawn(move || {
+            let (config, mut stats) = (RadioConfig::default(), LinkStats::new());
+            let mut heard = Vec::new();
+            let mut buf = [0; 64];
+            for answer in answers {
+                let len = radio.recv(&mut buf, Duration::from_secs(5)).unwrap();
+                heard.push(buf[..len].to_vec());
+                if answer {
+                    RoverMessage::CommandAck { timestamp: Default::default(), ack: true }
+                        .send(&mut radio, &config, &mut stats).unwrap();
+                }
+            }
+            heard
+        })
+    }
+
+    fn quick_acks() -> RadioConfig {
+        RadioConfig { timing: Timing { ack_timeout_ms: 100, msg_delay_ms: 0, listen_delay_ms: 10, ..Default::default() },
+                      ..Default::default() }
+    }
+
+    fn timestamp() -> RoverTimestamp {
+        RoverTimestamp { year: 21, month: 6, day: 14, hour: 12, minute: 3, second: 45 }
+    }
+
+    // serialize msg, then deserialize it into empty
+    fn round_trip(msg: &RoverMessage, mut empty: RoverMessage) -> RoverMessage {
+        let mut serialized = Vec::new();
+        msg.serialize(&mut serialized).unwrap();
+        empty.deserialize(&serialized).unwrap();
+        empty
+    }
+
+    #[test]
+    fn telemetry_message_round_trip() {
+        let msg = RoverMessage::TelemetryMessage { timestamp: timestamp(),
+                                                   location: RoverLocData { gps_lat: 37.42,
+                                                                            gps_long: -122.08,
+                                                                            gps_alt: 30.5,
+                                                                            gps_speed: 1.25,
+                                                                            gps_sats: 8,
+                                                                            gps_hdg: 271 },
+                                                   signal_strength: -72,
+                                                   free_memory: 1234,
+                                                   battery_millivolts: 7400,
+                                                   status: "OK".into() };
+        let empty = RoverMessage::TelemetryMessage { timestamp: timestamp(),
+                                                     location: Default::default(),
+                                                     signal_strength: 0,
+                                                     free_memory: 0,
+                                                     battery_millivolts: 0,
+                                                     status: String::new() };
+        assert_eq!(round_trip(&msg, empty), msg);
+    }
+
+    #[test]
+    fn telemetry_ack_round_trip() {
+        let msg = RoverMessage::TelemetryAck { timestamp: timestamp(), ack: true, command_waiting: true };
+        let empty = RoverMessage::TelemetryAck { timestamp: Default::default(), ack: false, command_waiting: false };
+        assert_eq!(round_trip(&msg, empty), msg);
+    }
+
+    #[test]
+    fn command_ready_round_trip() {
+        let msg = RoverMessage::CommandReady { timestamp: timestamp(), ready: true };
+        let empty = RoverMessage::CommandReady { timestamp: Default::default(), ready: false };
+        assert_eq!(round_trip(&msg, empty), msg);
+    }
+
+    #[test]
+    fn command_message_round_trip() {
+        let msg = RoverMessage::CommandMessage { timestamp: timestamp(),
+                                                 sequence_complete: true,
+                                                 command: "FWD 10".into() };
+        let empty = RoverMessage::CommandMessage { timestamp: Default::default(),
+                                                   sequence_complete: false,
+                                                   command: String::new() };
+        assert_eq!(round_trip(&msg, empty), msg);
+    }
+
+    #[test]
+    fn command_string_follows_sequence_complete() {
+        let command = "TURN LEFT 90";
+        let msg = RoverMessage::CommandMessage { timestamp: timestamp(),
+                                                 sequence_complete: false,
+                                                 command: command.into() };
+        let mut serialized = Vec::new();
+        msg.serialize(&mut serialized).unwrap();
+        assert_eq!(&serialized[BODY_START + 1..BODY_START + 1 + command.len()], command.as_bytes());
+        // stale bytes left in the buffer by an earlier receive must not leak into the string
+        let mut buf = [b'X'; 64];
+        buf[..serialized.len()].copy_from_slice(&serialized);
+        let mut empty = RoverMessage::CommandMessage { timestamp: Default::default(),
+                                                       sequence_complete: true,
+                                                       command: String::new() };
+        empty.deserialize(&buf[..serialized.len()]).unwrap();
+        assert_eq!(empty, msg);
+    }
+
+    #[test]
+    fn command_ack_round_trip() {
+        let msg = RoverMessage::CommandAck { timestamp: timestamp(), ack: true };
+        let empty = RoverMessage::CommandAck { timestamp: Default::default(), ack: false };
+        assert_eq!(round_trip(&msg, empty), msg);
+    }
+
+    #[test]
+    fn command_length_limit_depends_on_encryption() {
+        let mut config = RadioConfig::default();
+        let msg = RoverMessage::CommandMessage { timestamp: timestamp(),
+                                                 sequence_complete: true,
+                                                 command: "X".repeat(50) };
+        assert!(msg.check_string_length(&config).is_err());
+        config.use_encryption = false;
+        assert!(msg.check_string_length(&config).is_ok());
+    }
+
+    #[test]
+    fn unserializable_command_is_an_error() {
+        let msg = RoverMessage::CommandMessage { timestamp: timestamp(),
+                                                 sequence_complete: true,
+                                                 command: "DRIVE \u{201c}FAST\u{201d}".into() };
+        let mut radio = MockRadio::default();
+        match msg.send(&mut radio, &RadioConfig::default(), &mut LinkStats::new()) {
+            Err(Error(ErrorKind::SerializationError(_), _)) => (),
+            other => panic!("expected a SerializationError, got {:?}", other)
+        }
+        assert!(radio.sent.is_empty());
+    }
+
+    #[test]
+    fn long_command_is_fragmented() {
+        let command: String = (0..200).map(|i| (b'A' + (i % 26) as u8) as char).collect();
+        let msg = RoverMessage::CommandMessage { timestamp: timestamp(),
+                                                 sequence_complete: true,
+                                                 command: command.clone() };
+        let mut radio = MockRadio::default();
+        for sequence in 0..MAX_FRAGMENTS as u8 {
+            radio.queue_sequence(&RoverMessage::CommandAck { timestamp: timestamp(), ack: true }, sequence);
+        }
+        msg.send(&mut radio, &RadioConfig::default(), &mut LinkStats::new()).unwrap();
+        // reassemble the way the rover would
+        let mut reassembled = String::new();
+        for (index, frame) in radio.sent.iter().enumerate() {
+            let last = index == radio.sent.len() - 1;
+            let mut fragment = RoverMessage::CommandMessage { timestamp: Default::default(),
+                                                              sequence_complete: false,
+                                                              command: String::new() };
+            let header = fragment.deserialize(frame).unwrap();
+            assert_eq!(header.flags & FRAGMENT_INDEX_MASK, index as u8);
+            assert_eq!(header.flags & FLAG_MORE_FRAGMENTS != 0, !last);
+            match fragment {
+                RoverMessage::CommandMessage { sequence_complete, command, .. } => {
+                    assert_eq!(sequence_complete, last);
+                    reassembled.push_str(&command);
+                },
+                _ => unreachable!()
+            }
+        }
+        assert!(radio.sent.len() > 1);
+        assert_eq!(reassembled, command);
+    }
+
+    #[test]
+    fn request_telemetry_round_trip() {
+        let msg = RoverMessage::RequestTelemetry { timestamp: timestamp() };
+        let empty = RoverMessage::RequestTelemetry { timestamp: Default::default() };
+        assert_eq!(round_trip(&msg, empty), msg);
+    }
+
+    #[test]
+    fn request_telemetry_returns_reply() {
+        let telemetry = RoverMessage::TelemetryMessage { timestamp: timestamp(),
+                                                         location: Default::default(),
+                                                         signal_strength: -80,
+                                                         free_memory: 512,
+                                                         battery_millivolts: 7000,
+                                                         status: "POLLED".into() };
+        let mut radio = MockRadio::default();
+        radio.queue(&telemetry);
+        let request = RoverMessage::RequestTelemetry { timestamp: timestamp() };
+        let mut stats = LinkStats::new();
+        let reply = request.send(&mut radio, &RadioConfig::default(), &mut stats).unwrap();
+        assert_eq!(reply, Some(telemetry));
+        assert_eq!(stats.mean_rssi(), Some(-60.0));
+        // the request, then the station's TelemetryAck for the reply
+        assert_eq!(radio.sent.len(), 2);
+        assert_eq!(radio.sent[0][5], MESSAGE_REQUEST_TELEMETRY);
+        assert_eq!(radio.sent[1][5], MESSAGE_TELEMETRY_ACK);
+    }
+
+    #[test]
+    fn unanswered_request_times_out() {
+        let config = RadioConfig { timing: Timing { ack_timeout_ms: 5, msg_delay_ms: 0, listen_delay_ms: 1, send_attempts: 2, ..Default::default() },
+                                   ..Default::default() };
+        let mut radio = MockRadio::default();
+        let request = RoverMessage::RequestTelemetry { timestamp: timestamp() };
+        assert!(request.send(&mut radio, &config, &mut LinkStats::new()).is_err());
+        assert_eq!(radio.sent.len(), 2);
+    }
+
+    #[test]
+    fn command_is_acked_over_the_air() {
+        let (mut station, rover) = LoopbackRadio::pair();
+        let rover = acking_rover(rover, vec![true]);
+        let reply = RoverMessage::command(true, "STOP").send(&mut station, &quick_acks(), &mut LinkStats::new()).unwrap();
+        assert_eq!(reply, Some(RoverMessage::CommandAck { timestamp: Default::default(), ack: true }));
+        let heard = rover.join().unwrap();
+        assert_eq!(heard[0][MESSAGE_ID_OFFSET], MESSAGE_COMMAND);
+    }
+
+    #[test]
+    fn lost_command_is_retransmitted() {
+        let (mut station, rover) = LoopbackRadio::pair();
+        let rover = acking_rover(rover, vec![false, true]);
+        RoverMessage::command(true, "STOP").send(&mut station, &quick_acks(), &mut LinkStats::new()).unwrap();
+        // the same packet, sequence number and all, both times
+        let heard = rover.join().unwrap();
+        assert_eq!(heard[0], heard[1]);
+    }
+
+    #[test]
+    fn silent_rover_times_out() {
+        let (mut station, rover) = LoopbackRadio::pair();
+        let attempts = Timing::default().send_attempts as usize;
+        let rover = acking_rover(rover, vec![false; attempts]);
+        let e = RoverMessage::command(true, "STOP").send(&mut station, &quick_acks(), &mut LinkStats::new()).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::SendError(_)));
+        assert_eq!(rover.join().unwrap().len(), attempts);
+    }
+
+    #[test]
+    fn ping_records_rtt() {
+        let mut radio = MockRadio::default();
+        radio.queue(&RoverMessage::Pong { timestamp: timestamp() });
+        let mut stats = LinkStats::new();
+        let rtt = send_ping(&mut radio, &RadioConfig::default(), &mut stats).unwrap();
+        assert_eq!(stats.rtt(), Some(rtt));
+        assert_eq!(radio.sent.len(), 1);
+        assert_eq!(radio.sent[0][5], MESSAGE_PING);
+    }
+
+    #[test]
+    fn busy_channel_delays_sending() {
+        let config = RadioConfig { lbt: Some(ListenBeforeTalk { threshold_dbm: -90.0, attempts: 3, max_backoff_ms: 1 }),
+                                   ..Default::default() };
+        let ack = RoverMessage::telemetry_ack(true, false);
+        let mut radio = MockRadio { busy: 2, ..Default::default() };
+        ack.send(&mut radio, &config, &mut LinkStats::new()).unwrap();
+        assert_eq!(radio.sent.len(), 1);
+        // never clears: nothing goes out
+        let mut radio = MockRadio { busy: 3, ..Default::default() };
+        let e = ack.send(&mut radio, &config, &mut LinkStats::new()).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::SendError(_)));
+        assert!(radio.sent.is_empty());
+    }
+
+    #[test]
+    fn duplicate_is_acked_and_discarded() {
+        let telemetry = RoverMessage::TelemetryMessage { timestamp: timestamp(),
+                                                         location: Default::default(),
+                                                         signal_strength: -80,
+                                                         free_memory: 512,
+                                                         battery_millivolts: 7000,
+                                                         status: "OK".into() };
+        let mut radio = MockRadio::default();
+        // same sequence number both times, as if our first ack was lost
+        radio.queue(&telemetry);
+        radio.queue(&telemetry);
+        let config = RadioConfig::default();
+        let mut stats = LinkStats::new();
+        let mut received = RoverMessage::TelemetryMessage { timestamp: Default::default(),
+                                                            location: Default::default(),
+                                                            signal_strength: 0,
+                                                            free_memory: 0,
+                                                            battery_millivolts: 0,
+                                                            status: String::new() };
+        received.receive(&mut radio, &config, &mut stats, 100).unwrap();
+        assert_eq!(received, telemetry);
+        assert!(received.receive(&mut radio, &config, &mut stats, 100).is_err());
+        assert_eq!(radio.sent.len(), 2);
+        assert!(radio.sent.iter().all(|ack| ack[MESSAGE_ID_OFFSET] == MESSAGE_TELEMETRY_ACK));
+    }
+
+    #[test]
+    fn other_rovers_are_ignored() {
+        let mut radio = MockRadio::default();
+        radio.queue_header(&RoverMessage::CommandReady { timestamp: timestamp(), ready: false },
+                           &RadioHeadHeader { from: 2, ..Default::default() });
+        radio.queue_header(&RoverMessage::CommandReady { timestamp: timestamp(), ready: true },
+                           &RadioHeadHeader { from: 1, ..Default::default() });
+        let config = RadioConfig { rover_address: Some(1), ..Default::default() };
+        let mut ready = RoverMessage::CommandReady { timestamp: Default::default(), ready: false };
+        let header = ready.receive(&mut radio, &config, &mut LinkStats::new(), 1000).unwrap();
+        assert_eq!(header.from, 1);
+        assert_eq!(ready, RoverMessage::CommandReady { timestamp: timestamp(), ready: true });
+    }
+
+    #[test]
+    fn only_packets_for_this_station_are_taken() {
+        let mut radio = MockRadio::default();
+        radio.queue_header(&RoverMessage::CommandReady { timestamp: timestamp(), ready: false },
+                           &RadioHeadHeader { to: 3, ..Default::default() });
+        radio.queue_header(&RoverMessage::CommandReady { timestamp: timestamp(), ready: true },
+                           &RadioHeadHeader { to: 2, ..Default::default() });
+        let config = RadioConfig { station_node_id: 2, addressed_only: true, ..Default::default() };
+        let mut ready = RoverMessage::CommandReady { timestamp: Default::default(), ready: false };
+        let header = ready.receive(&mut radio, &config, &mut LinkStats::new(), 1000).unwrap();
+        assert_eq!(header.to, 2);
+        assert_eq!(ready, RoverMessage::CommandReady { timestamp: timestamp(), ready: true });
+        // and what the station sends is from it
+        let ack = RoverMessage::TelemetryAck { timestamp: timestamp(), ack: true, command_waiting: false };
+        ack.send(&mut radio, &config, &mut LinkStats::new()).unwrap();
+        assert_eq!(radio.sent[0][FROM_OFFSET], 2);
+    }
+
+    #[test]
+    fn sends_are_broadcast_or_addressed() {
+        let ack = RoverMessage::TelemetryAck { timestamp: timestamp(), ack: true, command_waiting: false };
+        let mut radio = MockRadio::default();
+        let broadcast = RadioConfig { rover_address: Some(4), station_node_id: 2, ..Default::default() };
+        ack.send(&mut radio, &broadcast, &mut LinkStats::new()).unwrap();
+        let addressed = RadioConfig { send_mode: SendMode::Addressed, ..broadcast };
+        ack.send(&mut radio, &addressed, &mut LinkStats::new()).unwrap();
+        let headers: Vec<&[u8]> = radio.sent.iter().map(|msg| &msg[TO_OFFSET..=FROM_OFFSET]).collect();
+        assert_eq!(headers, vec![&[BROADCAST_ADDRESS, 2][..], &[4, 2][..]]);
+        // with nobody to address, nothing is sent
+        let nobody = RadioConfig { send_mode: SendMode::Addressed, ..Default::default() };
+        assert!(ack.send(&mut radio, &nobody, &mut LinkStats::new()).is_err());
+        assert_eq!(radio.sent.len(), 2);
+    }
+
+    #[test]
+    fn sequence_numbers_count_per_message_type() {
+        let config = RadioConfig::default();
+        let mut stats = LinkStats::new();
+        let mut radio = MockRadio::default();
+        let ack = RoverMessage::TelemetryAck { timestamp: timestamp(), ack: true, command_waiting: false };
+        ack.send(&mut radio, &config, &mut stats).unwrap();
+        ack.send(&mut radio, &config, &mut stats).unwrap();
+        let sequences: Vec<u8> = radio.sent.iter().map(|msg| msg[3]).collect();  // the RadioHead ID byte
+        assert_eq!(sequences, vec![0, 1]);
+    }
+
+    #[test]
+    fn json_uses_field_names() {
+        let msg = RoverMessage::CommandAck { timestamp: timestamp(), ack: true };
+        assert_eq!(serde_json::to_string(&msg).unwrap(),
+                   r#"{"type":"CommandAck","timestamp":{"year":21,"month":6,"day":14,"hour":12,"minute":3,"second":45},"ack":true}"#);
+    }
+
+    #[test]
+    fn length_byte_counts_the_rest_of_the_packet() {
+        let telemetry = RoverMessage::TelemetryMessage { timestamp: timestamp(),
+                                                         location: Default::default(),
+                                                         signal_strength: -72,
+                                                         free_memory: 1024,
+                                                         battery_millivolts: 7400,
+                                                         status: "OK".into() };
+        for msg in [telemetry,
+                    RoverMessage::TelemetryAck { timestamp: timestamp(), ack: true, command_waiting: false },
+                    RoverMessage::CommandReady { timestamp: timestamp(), ready: true },
+                    RoverMessage::CommandMessage { timestamp: timestamp(), sequence_complete: true, command: "fwd 10".into() },
+                    RoverMessage::CommandAck { timestamp: timestamp(), ack: true },
+                    RoverMessage::RequestTelemetry { timestamp: timestamp() },
+                    RoverMessage::Ping { timestamp: timestamp() },
+                    RoverMessage::Pong { timestamp: timestamp() }] {
+            let mut buf = Vec::new();
+            msg.serialize(&mut buf).unwrap();
+            assert_eq!(buf[0] as usize, buf.len() - 1, "{}", msg);
+        }
+        // anything more than the length byte can count is an error, not a wrapped length
+        let long = RoverMessage::CommandMessage { timestamp: timestamp(), sequence_complete: true, command: "A".repeat(300) };
+        let mut buf = Vec::new();
+        match long.serialize(&mut buf) {
+            Err(Error(ErrorKind::SerializationError(_), _)) => (),
+            other => panic!("expected a SerializationError, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn impossible_fix_is_rejected() {
+        for location in [RoverLocData { gps_lat: 3e38, ..Default::default() },
+                         RoverLocData { gps_long: -180.5, ..Default::default() },
+                         RoverLocData { gps_lat: f32::NAN, ..Default::default() },
+                         RoverLocData { gps_alt: f32::INFINITY, ..Default::default() }] {
+            let msg = RoverMessage::TelemetryMessage { timestamp: timestamp(),
+                                                       location,
+                                                       signal_strength: -72,
+                                                       free_memory: 1024,
+                                                       battery_millivolts: 7400,
+                                                       status: "OK".into() };
+            let mut buf = Vec::new();
+            msg.serialize(&mut buf).unwrap();
+            let mut empty = RoverMessage::TelemetryMessage { timestamp: Default::default(),
+                                                             location: Default::default(),
+                                                             signal_strength: 0,
+                                                             free_memory: 0,
+                                                             battery_millivolts: 0,
+                                                             status: String::new() };
+            assert!(empty.deserialize(&buf).is_err());
+        }
+    }
+
+    #[test]
+    fn distance_and_bearing() {
+        let north = RoverLocData { gps_lat: 1.0, gps_long: 0.0, ..Default::default() };
+        let (distance, bearing) = north.distance_bearing_from(0.0, 0.0);
+        assert!((distance - 111_195.0).abs() < 1.0);
+        assert!(bearing.abs() < 1e-6);
+        let west = RoverLocData { gps_lat: 0.0, gps_long: -1.0, ..Default::default() };
+        let (_, bearing) = west.distance_bearing_from(0.0, 0.0);
+        assert!((bearing - 270.0).abs() < 1e-6);
+        assert!(north.has_fix());
+        assert!(!RoverLocData::default().has_fix());
+    }
+
+    #[test]
+    fn telemetry_ack_is_stamped_when_sent() {
+        // made up long before it's sent
+        let ack = RoverMessage::TelemetryAck { timestamp: timestamp(), ack: true, command_waiting: false };
+        let mut radio = MockRadio::default();
+        let before = RoverTimestamp::now();
+        ack.send(&mut radio, &RadioConfig::default(), &mut LinkStats::new()).unwrap();
+        let mut sent = RoverMessage::TelemetryAck { timestamp: timestamp(), ack: false, command_waiting: false };
+        sent.deserialize(&radio.sent[0]).unwrap();
+        match sent {
+            RoverMessage::TelemetryAck { timestamp: sent, .. } => {
+                assert_eq!(sent.year, before.year);
+                assert_ne!(sent, timestamp());
             },
-            _ => (), // no ack needed
+            _ => unreachable!()
+        }
+    }
+
+    #[test]
+    fn display_is_one_line() {
+        let msg = RoverMessage::TelemetryMessage { timestamp: timestamp(),
+                                                   location: RoverLocData { gps_lat: 37.42,
+                                                                            gps_long: -122.08,
+                                                                            gps_alt: 30.0,
+                                                                            gps_speed: 1.5,
+                                                                            gps_sats: 8,
+                                                                            gps_hdg: 270 },
+                                                   signal_strength: -72,
+                                                   free_memory: 1024,
+                                                   battery_millivolts: 7400,
+                                                   status: "OK".into() };
+        assert_eq!(msg.to_string(),
+                   "TELEM 12:03:45 rover_rssi=-72dBm sats=8 lat=37.42000 lon=-122.08000 alt=30m speed=1.5 hdg=270(W) batt=7400mV mem=1024 status=OK");
+        let msg = RoverMessage::CommandAck { timestamp: timestamp(), ack: true };
+        assert_eq!(msg.to_string(), "CMD_ACK 12:03:45 ack=true");
+    }
+
+    #[test]
+    fn coordinates_as_dms() {
+        let location = RoverLocData { gps_lat: 37.42, gps_long: -122.08, ..Default::default() };
+        assert_eq!(location.format_lat(CoordinateFormat::Dms), "37°25'12.0\"N");
+        assert_eq!(location.format_long(CoordinateFormat::Dms), "122°04'48.0\"W");
+        assert_eq!(location.format_long(CoordinateFormat::Decimal), "-122.08000");
+        // 10°59'59.99" rounds all the way up
+        let location = RoverLocData { gps_lat: -10.999_999, gps_long: 0.0, ..Default::default() };
+        assert_eq!(location.format_lat(CoordinateFormat::Dms), "11°00'00.0\"S");
+        assert_eq!(location.format_long(CoordinateFormat::Dms), "0°00'00.0\"E");
+    }
+
+    #[test]
+    fn compass_points() {
+        let point = |gps_hdg| RoverLocData { gps_hdg, ..Default::default() }.compass_point();
+        assert_eq!(point(0), "N");
+        assert_eq!(point(11), "N");
+        assert_eq!(point(12), "NNE");
+        assert_eq!(point(270), "W");
+        assert_eq!(point(348), "NNW");
+        assert_eq!(point(349), "N");
+        assert_eq!(point(360), "N");
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        let mut buf = Vec::new();
+        timestamp().serialize(&mut buf);
+        assert_eq!(buf, [21, 6, 14, 12, 3, 45]);
+        let mut decoded = RoverTimestamp { year: 0, month: 0, day: 0, hour: 0, minute: 0, second: 0 };
+        decoded.deserialize(&mut buf.as_slice());
+        assert_eq!(decoded, timestamp());
+    }
+
+    #[test]
+    fn default_timestamp_is_a_real_time() {
+        let now = RoverTimestamp::default();
+        assert!((1..=12).contains(&now.month));
+        assert!((1..=31).contains(&now.day));
+        assert!(now.hour < 24);
+        assert!(now.minute < 60);
+        // chrono reports a leap second as second 59 with extra nanoseconds
+        assert!(now.second < 60);
+    }
+
+    #[test]
+    fn unexpected_message_is_named() {
+        let mut frame = Vec::new();
+        RoverMessage::Pong { timestamp: timestamp() }.serialize(&mut frame).unwrap();
+        assert_eq!(peek_message_id(&frame), Some("MESSAGE_PONG"));
+        assert_eq!(peek_message_id(&frame[..3]), None);
+        frame[MESSAGE_ID_OFFSET] = 99;
+        assert_eq!(peek_message_id(&frame), None);
+
+        let mut radio = MockRadio::default();
+        radio.queue(&RoverMessage::Pong { timestamp: timestamp() });
+        let mut ack = RoverMessage::CommandAck { timestamp: timestamp(), ack: false };
+        let e = ack.receive(&mut radio, &RadioConfig::default(), &mut LinkStats::new(), 100).unwrap_err();
+        assert!(e.to_string().contains("expected MESSAGE_COMMAND_ACK, got MESSAGE_PONG"));
+    }
+
+    #[test]
+    fn only_good_expected_messages_are_acked() {
+        let telemetry = RoverMessage::TelemetryMessage { timestamp: timestamp(),
+                                                         location: Default::default(),
+                                                         signal_strength: -80,
+                                                         free_memory: 512,
+                                                         battery_millivolts: 7000,
+                                                         status: "OK".into() };
+        let config = RadioConfig::default();
+        let mut stats = LinkStats::new();
+        // telemetry while waiting for a CommandReady gets thrown away, so it mustn't be acked
+        let mut radio = MockRadio::default();
+        radio.queue(&telemetry);
+        let mut ready = RoverMessage::CommandReady { timestamp: timestamp(), ready: false };
+        match ready.receive(&mut radio, &config, &mut stats, 100) {
+            Err(Error(ErrorKind::ReceiveError(_), _)) => (),
+            other => panic!("expected a ReceiveError, got {:?}", other)
+        }
+        assert!(radio.sent.is_empty());
+        // and neither is telemetry that doesn't pass its checksum
+        let mut frame = Vec::new();
+        telemetry.serialize(&mut frame).unwrap();
+        frame[BODY_START] ^= 0xff;
+        radio.incoming.push_back(frame);
+        match RoverMessage::receive_any(&mut radio, &config, &mut stats, 100, false) {
+            Err(Error(ErrorKind::ReceiveError(_), _)) => (),
+            other => panic!("expected a ReceiveError, got {:?}", other.map(|_| ()))
+        }
+        assert!(radio.sent.is_empty());
+    }
+
+    #[test]
+    fn receive_any_takes_whatever_arrives() {
+        let mut radio = MockRadio::default();
+        radio.queue(&RoverMessage::CommandReady { timestamp: timestamp(), ready: true });
+        radio.queue(&RoverMessage::empty_telemetry());
+        let (config, mut stats) = (RadioConfig { timing: Timing { msg_delay_ms: 0, ..Default::default() }, ..Default::default() },
+                                   LinkStats::new());
+        let (message, _) = RoverMessage::receive_any(&mut radio, &config, &mut stats, 100, false).unwrap();
+        assert_eq!(message, RoverMessage::CommandReady { timestamp: timestamp(), ready: true });
+        assert!(radio.sent.is_empty());
+        // telemetry is acked on the way through
+        let (message, _) = RoverMessage::receive_any(&mut radio, &config, &mut stats, 100, true).unwrap();
+        assert_eq!(message.get_message_id(), MESSAGE_TELEMETRY);
+        assert_eq!(radio.sent.len(), 1);
+        assert_eq!(radio.sent[0][MESSAGE_ID_OFFSET], MESSAGE_TELEMETRY_ACK);
+    }
+
+    #[test]
+    fn jitter_breaks_lockstep_with_the_rover() {
+        // a rover sending every 50ms to a radio that only has the packet for
+        // 5ms of that, listened to 50ms at a time starting 10ms out of step.
+        // a timeout of 0 is one listen per receive.
+        let captured = |listen_jitter_ms| {
+            let beacon = Beacon { msg: RoverMessage::CommandReady { timestamp: timestamp(), ready: true },
+                                  period: Duration::from_millis(50),
+                                  on_air: Duration::from_millis(5),
+                                  now: Duration::from_millis(10) };
+            let mut radio = MockRadio { beacon: Some(beacon), ..Default::default() };
+            let config = RadioConfig { timing: Timing { listen_delay_ms: 50, listen_jitter_ms, ..Default::default() },
+                                       ..Default::default() };
+            let mut stats = LinkStats::new();
+            (0..200).filter(|_| RoverMessage::receive_any(&mut radio, &config, &mut stats, 0, false).is_ok()).count()
+        };
+        let (lockstep, jittered) = (captured(0), captured(20));
+        assert_eq!(lockstep, 0);
+        assert!(jittered > lockstep, "{}", jittered);
+    }
+
+    #[test]
+    fn protocol_version_survives_round_trip() {
+        let messages = [RoverMessage::TelemetryAck { timestamp: timestamp(), ack: true, command_waiting: false },
+                        RoverMessage::CommandReady { timestamp: timestamp(), ready: true },
+                        RoverMessage::CommandAck { timestamp: timestamp(), ack: true },
+                        RoverMessage::RequestTelemetry { timestamp: timestamp() }];
+        for msg in messages.iter() {
+            let mut serialized = Vec::new();
+            msg.serialize(&mut serialized).unwrap();
+            // right after the message id
+            assert_eq!(serialized[MESSAGE_ID_OFFSET + 1], PROTOCOL_VERSION);
+            let mut empty = RoverMessage::empty(msg.get_message_id()).unwrap();
+            empty.deserialize(&serialized).unwrap();
+            assert_eq!(&empty, msg);
+        }
+    }
+
+    #[test]
+    fn protocol_version_is_checked() {
+        let msg = RoverMessage::CommandAck { timestamp: timestamp(), ack: true };
+        let mut serialized = Vec::new();
+        msg.serialize(&mut serialized).unwrap();
+        assert_eq!(serialized[6], PROTOCOL_VERSION);
+        let mut buf = [0 as u8; 64];
+        buf[..serialized.len()].copy_from_slice(&serialized);
+        buf[6] = PROTOCOL_VERSION - 1;
+        let checksum_at = serialized.len() - 1;
+        buf[checksum_at] = RoverMessage::crc8(&buf[1..checksum_at]);
+        let mut empty = RoverMessage::CommandAck { timestamp: Default::default(), ack: false };
+        let e = empty.deserialize(&buf[..serialized.len()]).unwrap_err();
+        assert!(e.to_string().contains("protocol version"));
+    }
+
+    #[test]
+    fn strict_bools_are_0_or_1() {
+        let msg = RoverMessage::CommandAck { timestamp: timestamp(), ack: true };
+        let mut serialized = Vec::new();
+        msg.serialize(&mut serialized).unwrap();
+        let checksum_at = serialized.len() - 1;
+        serialized[BODY_START] = 0x7f;
+        serialized[checksum_at] = RoverMessage::crc8(&serialized[1..checksum_at]);
+        let mut empty = RoverMessage::CommandAck { timestamp: Default::default(), ack: false };
+        empty.deserialize(&serialized).unwrap();
+        assert_eq!(empty, msg);
+        match empty.deserialize_with(&serialized, true) {
+            Err(Error(ErrorKind::ReceiveError(message), _)) => assert_eq!(message, "flag byte is 0x7f, not 0 or 1"),
+            other => panic!("expected a ReceiveError, got {:?}", other)
+        }
+        serialized[BODY_START] = 1;
+        serialized[checksum_at] = RoverMessage::crc8(&serialized[1..checksum_at]);
+        empty.deserialize_with(&serialized, true).unwrap();
+        assert_eq!(empty, msg);
+    }
+
+    #[test]
+    fn only_received_bytes_are_used() {
+        let msg = RoverMessage::CommandAck { timestamp: timestamp(), ack: true };
+        let mut serialized = Vec::new();
+        msg.serialize(&mut serialized).unwrap();
+        let mut buf = [0 as u8; 64];
+        buf[..serialized.len()].copy_from_slice(&serialized);
+        let mut empty = RoverMessage::CommandAck { timestamp: Default::default(), ack: false };
+        // the checksum is in the buffer, but it wasn't part of this packet
+        assert!(empty.deserialize(&buf[..serialized.len() - 1]).is_err());
+        assert!(empty.deserialize(&buf[..serialized.len()]).is_ok());
+    }
+
+    #[test]
+    fn oversized_packet_is_rejected() {
+        let mut radio = MockRadio::default();
+        let mut garbled = vec![0; 64];
+        garbled[0] = 200;
+        radio.incoming.push_back(garbled);
+        let mut short = vec![0; 20];
+        short[0] = 30;
+        radio.incoming.push_back(short);
+        let (config, mut stats) = (RadioConfig::default(), LinkStats::new());
+        let e = RoverMessage::receive_any(&mut radio, &config, &mut stats, 100, false).unwrap_err();
+        assert!(e.to_string().contains("more than the 64 byte buffer"));
+        let e = RoverMessage::receive_any(&mut radio, &config, &mut stats, 100, false).unwrap_err();
+        assert!(e.to_string().contains("only 20 were received"));
+        assert_eq!(stats.messages.malformed(), 2);
+    }
+
+    #[test]
+    fn radiohead_header_round_trip() {
+        let header = RadioHeadHeader { to: 3, from: 1, id: 42, flags: 0x02 };
+        let msg = RoverMessage::CommandAck { timestamp: timestamp(), ack: true };
+        let mut serialized = Vec::new();
+        msg.serialize_packet(&mut serialized, &header).unwrap();
+        let mut empty = RoverMessage::CommandAck { timestamp: Default::default(), ack: false };
+        assert_eq!(empty.deserialize(&serialized).unwrap(), header);
+        assert_eq!(empty, msg);
+    }
+
+    #[test]
+    fn frame_dump_labels_header() {
+        let msg = RoverMessage::CommandAck { timestamp: timestamp(), ack: true };
+        let mut serialized = Vec::new();
+        msg.serialize(&mut serialized).unwrap();
+        let dump = format_frame("sent", &serialized);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], format!("sent frame, {} bytes:", serialized.len()));
+        assert_eq!(lines[1], format!("  00: {:02x}  length ({})", serialized[0], serialized[0]));
+        assert_eq!(lines[6], "  05: 04  message id (MESSAGE_COMMAND_ACK)");
+        assert_eq!(lines[8], "  07: 15 06 0e 0c 03 2d 01 ".to_owned() + &format!("{:02x}", serialized[14]));
+    }
+
+    #[test]
+    fn checksum_known_value() {
+        // the standard CRC-8/SMBUS check value
+        assert_eq!(RoverMessage::crc8(b"123456789"), 0xf4);
+    }
+
+    #[test]
+    fn corrupted_byte_fails_checksum() {
+        let msg = RoverMessage::CommandMessage { timestamp: timestamp(),
+                                                 sequence_complete: true,
+                                                 command: "DRIVE 10".into() };
+        let mut serialized = Vec::new();
+        msg.serialize(&mut serialized).unwrap();
+        let mut buf = [0 as u8; 64];
+        buf[..serialized.len()].copy_from_slice(&serialized);
+        buf[BODY_START + 2] ^= 0x20;  // "DRIVE" -> "DrIVE"
+        let mut empty = RoverMessage::CommandMessage { timestamp: Default::default(),
+                                                       sequence_complete: false,
+                                                       command: String::new() };
+        let e = empty.deserialize(&buf[..serialized.len()]).unwrap_err();
+        assert!(e.to_string().contains("checksum"));
+        // nothing was filled in from the bad packet
+        match empty {
+            RoverMessage::CommandMessage { sequence_complete, command, .. } => {
+                assert!(!sequence_complete);
+                assert!(command.is_empty());
+            },
+            _ => unreachable!()
         }
-        Ok(())
     }
 }
@@ -0,0 +1,129 @@
+// a summary of the whole mission, written as JSON on shutdown for looking
+// over afterwards: how much got through and how well, how long it went on,
+// and where the rover started and finished. MissionTracker keeps the tally
+// from the telemetry as it arrives; the rest comes from the link stats.
+
+use crate::errors::*;
+use crate::link_stats::LinkStats;
+use crate::messages::*;
+use chrono::{ DateTime, Utc };
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ BufWriter, Write };
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Position {
+    pub lat: f32,
+    pub lon: f32,
+    pub time: Option<DateTime<Utc>>,  // the rover's timestamp, if it was a real time
+}
+
+pub struct MissionTracker {
+    started: DateTime<Utc>,
+    fixes: u64,
+    first_position: Option<Position>,
+    last_position: Option<Position>,
+}
+
+impl MissionTracker {
+    pub fn new() -> MissionTracker {
+        MissionTracker { started: Utc::now(), fixes: 0, first_position: None, last_position: None }
+    }
+
+    pub fn record(&mut self, timestamp: &RoverTimestamp, location: &RoverLocData) {
+        if !location.has_fix() {
+            return;
+        }
+        self.fixes += 1;
+        let position = Position { lat: location.gps_lat, lon: location.gps_long, time: timestamp.to_datetime() };
+        if self.first_position.is_none() {
+            self.first_position = Some(position.clone());
+        }
+        self.last_position = Some(position);
+    }
+
+    pub fn report(&self, stats: &LinkStats) -> MissionReport {
+        let ended = Utc::now();
+        let messages = &stats.messages;
+        MissionReport {
+            started: self.started,
+            ended,
+            duration_secs: (ended - self.started).num_seconds(),
+            packets: messages.received()
+                .map(|(id, count)| (RoverMessage::get_message_type(id).to_string(), count)).collect(),
+            malformed_packets: messages.malformed(),
+            unknown_packets: messages.unknown(),
+            crc_failures: stats.crc_failures(),
+            crc_failure_rate: stats.crc_failure_rate(),
+            min_rssi_dbm: stats.min_rssi(),
+            max_rssi_dbm: stats.max_rssi(),
+            mean_rssi_dbm: stats.mean_rssi(),
+            mean_rtt_ms: stats.mean_rtt().map(|rtt| rtt.as_secs_f64() * 1000.0),
+            gps_fixes: self.fixes,
+            first_position: self.first_position.clone(),
+            last_position: self.last_position.clone()
+        }
+    }
+}
+
+// the signal strengths are over the recent history kept in LinkStats, not the
+// whole mission
+#[derive(Debug, Serialize)]
+pub struct MissionReport {
+    pub started: DateTime<Utc>,
+    pub ended: DateTime<Utc>,
+    pub duration_secs: i64,
+    pub packets: BTreeMap<String, u64>,  // by message type
+    pub malformed_packets: u64,
+    pub unknown_packets: u64,
+    pub crc_failures: u64,
+    pub crc_failure_rate: Option<f32>,
+    pub min_rssi_dbm: Option<f32>,
+    pub max_rssi_dbm: Option<f32>,
+    pub mean_rssi_dbm: Option<f32>,
+    pub mean_rtt_ms: Option<f64>,
+    pub gps_fixes: u64,
+    pub first_position: Option<Position>,
+    pub last_position: Option<Position>,
+}
+
+impl MissionReport {
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut out = BufWriter::new(File::create(path).chain_err(|| format!("creating mission report {}", path.display()))?);
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn report_sums_up_the_mission() {
+        let mut tracker = MissionTracker::new();
+        let at = |second| RoverTimestamp { year: 21, month: 6, day: 14, hour: 12, minute: 0, second };
+        tracker.record(&at(0), &RoverLocData::default());  // no fix yet
+        tracker.record(&at(10), &RoverLocData { gps_lat: 37.5, gps_long: -122.25, gps_sats: 6, ..Default::default() });
+        tracker.record(&at(20), &RoverLocData { gps_lat: 37.75, gps_long: -122.5, gps_sats: 6, ..Default::default() });
+        let mut stats = LinkStats::new();
+        stats.record_rssi(-70.0);
+        stats.record_rtt(Duration::from_millis(100));
+        stats.record_rtt(Duration::from_millis(200));
+        stats.messages.record(0);
+        let report = tracker.report(&stats);
+        assert_eq!(report.gps_fixes, 2);
+        assert_eq!(report.first_position.as_ref().map(|p| p.lat), Some(37.5));
+        assert_eq!(report.last_position.as_ref().map(|p| p.lon), Some(-122.5));
+        assert_eq!(report.mean_rtt_ms, Some(150.0));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["packets"]["MESSAGE_TELEMETRY"], 1);
+        assert_eq!(json["first_position"]["time"], "2021-06-14T12:00:10Z");
+    }
+}
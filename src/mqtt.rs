@@ -0,0 +1,72 @@
+// publishes received telemetry to an MQTT broker as JSON, for dashboards and
+// anything else on the network that wants to follow the rover
+
+use crate::errors::*;
+use crate::messages::*;
+use log::warn;
+use rumqttc::{ Client, MqttOptions, QoS };
+use serde::Deserialize;
+use std::thread;
+use std::time::Duration;
+
+const CLIENT_ID: &str = "ground_control";
+const KEEP_ALIVE_SECS: u64 = 30;
+const QUEUE_SIZE: usize = 16;        // telemetry waiting to go out to the broker; newer messages are dropped once full
+const RECONNECT_DELAY: u64 = 5000;   // millis between attempts to reach the broker
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+    pub username: Option<String>,  // only used if password is set too
+    pub password: Option<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: String::from("localhost"),
+            port: 1883,
+            topic: String::from("rover/telemetry"),
+            username: None,
+            password: None
+        }
+    }
+}
+
+pub struct MqttPublisher {
+    client: Client,
+    topic: String,
+}
+
+impl MqttPublisher {
+    // the connection to the broker runs on its own thread, which keeps
+    // reconnecting for as long as the broker is unreachable. publishing only
+    // ever queues, so a dead broker can't hold up the receive loop.
+    pub fn new(config: &MqttConfig) -> MqttPublisher {
+        let mut options = MqttOptions::new(CLIENT_ID, config.host.as_str(), config.port);
+        options.set_keep_alive(Duration::from_secs(KEEP_ALIVE_SECS));
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            options.set_credentials(username.as_str(), password.as_str());
+        }
+        let (client, mut connection) = Client::new(options, QUEUE_SIZE);
+        thread::spawn(move || {
+            for event in connection.iter() {
+                if let Err(e) = event {
+                    warn!("MQTT connection error: {}", e);
+                    thread::sleep(Duration::from_millis(RECONNECT_DELAY));
+                }
+            }
+        });
+        MqttPublisher { client, topic: config.topic.clone() }
+    }
+
+    pub fn publish(&mut self, telemetry: &RoverMessage) -> Result<()> {
+        let payload = serde_json::to_vec(telemetry)?;
+        self.client.try_publish(self.topic.as_str(), QoS::AtMostOnce, false, payload)
+            .map_err(|e| format!("MQTT publish failed: {}", e))?;
+        Ok(())
+    }
+}
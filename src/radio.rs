@@ -0,0 +1,448 @@
+// radio abstraction used by the message protocol, so that RoverMessage::send /
+// receive don't need to know what hardware (if any) is on the other end
+
+use crate::errors::*;
+use crate::keyfile;
+use crate::messages::BROADCAST_ADDRESS;
+use rfm69::{ Rfm69, registers::{ Mode, Registers } };
+use rppal::{ gpio::{ InputPin, OutputPin, Trigger }, spi::{ Bus, SlaveSelect, Spi } };
+use serde::{ de, Deserialize, Deserializer };
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{ Duration, Instant };
+
+// RegIrqFlags2 bit saying the packet in the FIFO passed its CRC
+const CRC_OK: u8 = 0b10;
+
+// RegRssiConfig bits
+const RSSI_START: u8 = 0b01;
+const RSSI_DONE: u8 = 0b10;
+const RSSI_TIMEOUT: Duration = Duration::from_millis(10);
+
+// the high power settings for PA_BOOST (RFM69HCW datasheet section 3.3.7),
+// which add 3 dB on top of PA1 and PA2. they're only allowed while
+// transmitting, with the overcurrent protection off, so send() turns them on
+// for each packet and puts everything back afterwards. the normal values are
+// the ones the chip resets to.
+const TEST_PA1_NORMAL: u8 = 0x55;
+const TEST_PA1_HIGH_POWER: u8 = 0x5d;
+const TEST_PA2_NORMAL: u8 = 0x70;
+const TEST_PA2_HIGH_POWER: u8 = 0x7c;
+const OCP_ON: u8 = 0x1a;   // OcpOn, limited to 95 mA
+const OCP_OFF: u8 = 0x0f;
+pub const MIN_HIGH_POWER_LEVEL: u8 = 16;  // +5 dBm; below this PA1 and PA2 alone will do
+
+// every register worth showing in a dump, in address order. the FIFO is left
+// out, since reading it takes a byte out of it, and so is the AES key, which
+// is write only.
+const DUMPED_REGISTERS: [Registers; 68] = [
+    Registers::OpMode, Registers::DataModul, Registers::BitrateMsb, Registers::BitrateLsb, Registers::FdevMsb,
+    Registers::FdevLsb, Registers::FrfMsb, Registers::FrfMid, Registers::FrfLsb, Registers::Osc1, Registers::AfcCtrl,
+    Registers::LowBat, Registers::Listen1, Registers::Listen2, Registers::Listen3, Registers::Version,
+    Registers::PaLevel, Registers::PaRamp, Registers::Ocp, Registers::AgcRef, Registers::AgcThresh1,
+    Registers::AgcThresh2, Registers::AgcThresh3, Registers::Lna, Registers::RxBw, Registers::AfcBw,
+    Registers::OokPeak, Registers::OokAvg, Registers::OokFix, Registers::AfcFei, Registers::AfcMsb, Registers::AfcLsb,
+    Registers::FeiMsb, Registers::FeiLsb, Registers::RssiConfig, Registers::RssiValue, Registers::DioMapping1,
+    Registers::DioMapping2, Registers::IrqFlags1, Registers::IrqFlags2, Registers::RssiThresh, Registers::RxTimeout1,
+    Registers::RxTimeout2, Registers::PreambleMsb, Registers::PreambleLsb, Registers::SyncConfig,
+    Registers::SyncValue1, Registers::SyncValue2, Registers::SyncValue3, Registers::SyncValue4, Registers::SyncValue5,
+    Registers::SyncValue6, Registers::SyncValue7, Registers::SyncValue8, Registers::PacketConfig1,
+    Registers::PayloadLength, Registers::NodeAddrs, Registers::BroadcastAddrs, Registers::AutoModes,
+    Registers::FifoThresh, Registers::PacketConfig2, Registers::Temp1, Registers::Temp2,
+    Registers::TestLna, Registers::TestPa1, Registers::TestPa2, Registers::TestDagc, Registers::TestAfc,
+];
+
+// tunable radio settings, used both to set up the RFM69 and by the message
+// protocol, so the two can't disagree. the defaults match what the rover
+// firmware expects.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RadioConfig {
+    pub frequency_hz: f64,     // carrier frequency; bonnets come in 868 and 915 MHz variants
+    pub power_level: u8,       // 0-31, output power is (-14 + power_level) dBm with PA1 and PA2 on
+    pub high_power: bool,      // PA_BOOST high power settings: (-11 + power_level) dBm, up to +20
+    pub key_path: PathBuf,     // AES key and sync words, see keyfile.rs
+    pub use_encryption: bool,  // AES on the radio; limits packets to 64 bytes
+    pub bit_rate: f32,         // bits per second
+    pub fdev_hz: f32,          // FSK frequency deviation
+    pub fdev_raw: Option<u16>, // exact Fdev register value, overriding the one computed from fdev_hz
+    pub rover_address: Option<u8>,  // only listen to packets FROM this node id; None hears every rover
+    pub station_node_id: u8,   // this station's RadioHead node id, sent as FROM
+    pub addressed_only: bool,  // only listen to packets sent TO station_node_id (or broadcast)
+    pub send_mode: SendMode,   // who packets are sent TO
+    // once a packet has been read out, the receiver waits this many bit times
+    // (a power of two, 1 to 2048) before listening for the next. it's there to
+    // let the transmitter's PA ramp down, so it only needs to be long enough
+    // for the rover's radio; any longer and a quick reply could be missed.
+    pub interpacket_rx_delay_bits: u16,
+    // restart the receiver by itself after each packet (after the delay
+    // above). without it the receiver sits idle after a packet until it's
+    // put back into receive mode, which the station only does the next time
+    // it listens.
+    pub auto_rx_restart: bool,
+    // only take exactly 0 or 1 for the ack, ready and sequence_complete
+    // flags, refusing a packet with anything else in one. otherwise any
+    // nonzero byte is true, as the rover's C++ would have it.
+    pub strict_bools: bool,
+    pub pins: PinConfig,
+    pub timing: Timing,
+    pub lbt: Option<ListenBeforeTalk>,  // carrier sense before transmitting; None transmits straight away
+}
+
+impl Default for RadioConfig {
+    fn default() -> Self {
+        Self {
+            frequency_hz: 915_000_000.0,
+            power_level: 31,  // +17 dBm
+            high_power: false,
+            key_path: keyfile::default_key_path(),
+            use_encryption: true,
+            bit_rate: 9600.0,
+            // nominally 19200 Hz, but the register value computed from that is
+            // off by 1 from what the rover has. this computes to its 0x0138.
+            fdev_hz: 19043.0,
+            fdev_raw: None,
+            rover_address: None,
+            station_node_id: BROADCAST_ADDRESS,  // what RadioHead uses until it's given an address
+            addressed_only: false,
+            send_mode: SendMode::Broadcast,
+            interpacket_rx_delay_bits: 1,
+            auto_rx_restart: true,
+            strict_bools: false,
+            pins: Default::default(),
+            timing: Default::default(),
+            lbt: None
+        }
+    }
+}
+
+// how the RFM69 is wired to the Pi. the defaults are the Adafruit radio
+// bonnet's wiring, which is what setup_radio always used before these were
+// configurable.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PinConfig {
+    pub cs_pin: u8,     // BCM GPIO number of the chip select line
+    pub reset_pin: u8,  // BCM GPIO number of the reset line
+    pub dio0_pin: u8,   // BCM GPIO number DIO0 is wired to; it signals that a packet has arrived
+    #[serde(deserialize_with = "spi_bus")]
+    pub spi_bus: Bus,   // given as a number in the config file, e.g. 1 for SPI1
+    #[serde(deserialize_with = "slave_select")]
+    pub slave_select: SlaveSelect,
+}
+
+impl Default for PinConfig {
+    fn default() -> Self {
+        Self {
+            cs_pin: 7,
+            reset_pin: 25,
+            dio0_pin: 22,
+            spi_bus: Bus::Spi0,
+            slave_select: SlaveSelect::Ss0
+        }
+    }
+}
+
+// how long the message protocol waits for things, in millis. the defaults
+// suit the default bit rate over a few hundred meters; a slower or longer
+// link may need a longer ack timeout. the RFM69 holds on to a packet until
+// it's read, so it can't be missed between listens, but a radio that only
+// has a packet while it's being polled can, over and over if the rover
+// transmits in step with listen_delay_ms. some jitter breaks that up.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timing {
+    pub ack_timeout_ms: u64,  // to wait for an ack msg
+    pub msg_delay_ms: u64,    // between Rx and Tx, to give the other side time to switch from Tx to Rx
+    pub listen_delay_ms: u64, // to wait for a packet at a time before checking the receive timeout again
+    pub listen_jitter_ms: u64, // up to this much more, chosen at random each time, added to listen_delay_ms
+    pub send_attempts: u64,   // not a time: how many times to transmit a message that expects an ack
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            ack_timeout_ms: 1000,
+            msg_delay_ms: 100,
+            listen_delay_ms: 50,
+            listen_jitter_ms: 0,
+            send_attempts: 3
+        }
+    }
+}
+
+// listen-before-talk: before each transmission the channel's RSSI is read,
+// and while it's above threshold_dbm (someone, probably the rover, is already
+// transmitting) we back off for a random time up to max_backoff_ms and listen
+// again, giving up after `attempts` busy readings.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenBeforeTalk {
+    pub threshold_dbm: f32,
+    pub attempts: u32,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for ListenBeforeTalk {
+    fn default() -> Self {
+        Self {
+            threshold_dbm: -90.0,
+            attempts: 5,
+            max_backoff_ms: 100
+        }
+    }
+}
+
+// rppal's SPI enums don't implement Deserialize, so the config file numbers them
+const SPI_BUSES: [Bus; 7] = [Bus::Spi0, Bus::Spi1, Bus::Spi2, Bus::Spi3, Bus::Spi4, Bus::Spi5, Bus::Spi6];
+const SLAVE_SELECTS: [SlaveSelect; 16] = [SlaveSelect::Ss0, SlaveSelect::Ss1, SlaveSelect::Ss2, SlaveSelect::Ss3,
+                                          SlaveSelect::Ss4, SlaveSelect::Ss5, SlaveSelect::Ss6, SlaveSelect::Ss7,
+                                          SlaveSelect::Ss8, SlaveSelect::Ss9, SlaveSelect::Ss10, SlaveSelect::Ss11,
+                                          SlaveSelect::Ss12, SlaveSelect::Ss13, SlaveSelect::Ss14, SlaveSelect::Ss15];
+
+fn spi_bus<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Bus, D::Error> {
+    let n = usize::deserialize(deserializer)?;
+    SPI_BUSES.get(n).copied().ok_or_else(|| de::Error::custom(format!("no SPI bus {}", n)))
+}
+
+fn slave_select<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<SlaveSelect, D::Error> {
+    let n = usize::deserialize(deserializer)?;
+    SLAVE_SELECTS.get(n).copied().ok_or_else(|| de::Error::custom(format!("no SPI slave select {}", n)))
+}
+
+// every rover in range hears a broadcast, so to talk to just one, address
+// the packets to rover_address
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SendMode {
+    #[default]
+    Broadcast,
+    Addressed,
+}
+
+impl RadioConfig {
+    // largest packet (including the length byte) the radio will carry
+    pub fn max_message_length(&self) -> usize {
+        if self.use_encryption { 64 } else { 255 }
+    }
+
+    // the TO byte for everything the station sends
+    pub fn send_to(&self) -> Result<u8> {
+        match (self.send_mode, self.rover_address) {
+            (SendMode::Broadcast, _) => Ok(BROADCAST_ADDRESS),
+            (SendMode::Addressed, Some(rover)) => Ok(rover),
+            (SendMode::Addressed, None) => Err(ErrorKind::RadioError("addressed sends need a rover_address to send to".into()).into())
+        }
+    }
+}
+
+// rfm69::Error doesn't implement std::error::Error (or Display), so it can't be
+// a foreign link in errors.rs as-is. this mirrors its variants so callers can
+// still tell a timeout from an SPI failure.
+#[derive(Debug)]
+pub enum Rfm69Error {
+    Cs(String),
+    Spi(String),
+    Timeout,
+    AesKeySize,
+    SyncSize,
+    BufferTooSmall,
+    PacketTooLarge,
+    Crc,  // not one of rfm69's; see Rfm69Radio::read_packet
+}
+
+impl<Ecs: fmt::Debug, Espi: fmt::Debug> From<rfm69::Error<Ecs, Espi>> for Rfm69Error {
+    fn from(e: rfm69::Error<Ecs, Espi>) -> Self {
+        match e {
+            rfm69::Error::Cs(e) => Rfm69Error::Cs(format!("{:?}", e)),
+            rfm69::Error::Spi(e) => Rfm69Error::Spi(format!("{:?}", e)),
+            rfm69::Error::Timeout => Rfm69Error::Timeout,
+            rfm69::Error::AesKeySize => Rfm69Error::AesKeySize,
+            rfm69::Error::SyncSize => Rfm69Error::SyncSize,
+            rfm69::Error::BufferTooSmall => Rfm69Error::BufferTooSmall,
+            rfm69::Error::PacketTooLarge => Rfm69Error::PacketTooLarge,
+        }
+    }
+}
+
+impl fmt::Display for Rfm69Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rfm69Error::Cs(e) => write!(f, "chip select error: {}", e),
+            Rfm69Error::Spi(e) => write!(f, "SPI error: {}", e),
+            Rfm69Error::Timeout => write!(f, "timed out"),
+            Rfm69Error::AesKeySize => write!(f, "AES key must be 16 bytes"),
+            Rfm69Error::SyncSize => write!(f, "sync must be 1-8 bytes"),
+            Rfm69Error::BufferTooSmall => write!(f, "buffer too small"),
+            Rfm69Error::PacketTooLarge => write!(f, "packet too large"),
+            Rfm69Error::Crc => write!(f, "packet failed its CRC"),
+        }
+    }
+}
+
+impl std::error::Error for Rfm69Error {}
+
+pub trait RadioLink {
+    // transmit one packet. buf already starts with the length byte.
+    fn send(&mut self, buf: &[u8]) -> Result<()>;
+
+    // wait up to `wait` for a packet and receive it into buf, returning the
+    // number of bytes received (including the length byte), or 0 if nothing
+    // arrived in that time.
+    fn recv(&mut self, buf: &mut [u8], wait: Duration) -> Result<usize>;
+
+    // signal strength of the last received packet, in dBm
+    fn rssi(&self) -> f32;
+
+    // signal strength on the channel right now, for carrier sensing, in dBm
+    fn channel_rssi(&mut self) -> Result<f32>;
+}
+
+// the RFM69 along with the GPIOs its reset and DIO0 lines are wired to.
+// setup_radio maps DIO0 to PayloadReady, so rather than polling the radio over
+// SPI we can sleep on a GPIO interrupt until a packet is actually there. the
+// reset line is kept so a wedged radio can be restarted.
+pub struct Rfm69Radio {
+    pub rfm: Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>,
+    pub reset: OutputPin,
+    dio0: InputPin,
+    high_power: bool,  // switch the high power settings on for each transmission
+}
+
+impl Rfm69Radio {
+    pub fn new(rfm: Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>, reset: OutputPin, mut dio0: InputPin,
+               high_power: bool) -> Result<Rfm69Radio> {
+        dio0.set_interrupt(Trigger::RisingEdge).chain_err(|| ErrorKind::RadioError("setting DIO0 interrupt".into()))?;
+        Ok(Rfm69Radio { rfm, reset, dio0, high_power })
+    }
+
+    // OCP has to go off before the test registers go up, and come back on
+    // after they're down again
+    fn high_power_settings(&mut self, on: bool) -> Result<()> {
+        let writes = if on {
+            [(Registers::Ocp, OCP_OFF), (Registers::TestPa1, TEST_PA1_HIGH_POWER), (Registers::TestPa2, TEST_PA2_HIGH_POWER)]
+        } else {
+            [(Registers::TestPa1, TEST_PA1_NORMAL), (Registers::TestPa2, TEST_PA2_NORMAL), (Registers::Ocp, OCP_ON)]
+        };
+        for (register, value) in writes.iter() {
+            self.rfm.write(*register, *value).map_err(Rfm69Error::from)?;
+        }
+        Ok(())
+    }
+
+    // read the waiting packet out of the FIFO. the radio is set up to keep
+    // packets that fail their CRC (see configure_radio), so those are read out
+    // too, to clear the FIFO, but come back as an Rfm69Error::Crc.
+    fn read_packet(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let crc_ok = self.rfm.read(Registers::IrqFlags2).map_err(Rfm69Error::from)? & CRC_OK != 0;
+        // recv claims it "blocks until there are any bytes available"
+        // but this is a lie; it actually has a hardcoded timeout of 100ms
+        // and returns a timeout error if there are no packets in that time.
+        // with DIO0 already high it returns straight away.
+        match self.rfm.recv(buf) {
+            // rfm69 doesn't tell us how much it read, but the first byte
+            // of a variable length packet is the length of the rest of it
+            Ok(_) if !crc_ok => Err(Rfm69Error::Crc.into()),
+            Ok(_) => Ok(buf.first().map_or(0, |len| (*len as usize + 1).min(buf.len()))),
+            Err(rfm69::Error::Timeout) => Ok(0),
+            Err(e) => Err(Rfm69Error::from(e).into())
+        }
+    }
+}
+
+impl RadioLink for Rfm69Radio {
+    fn send(&mut self, buf: &[u8]) -> Result<()> {
+        if !self.high_power {
+            self.rfm.send(buf).map_err(Rfm69Error::from)?;
+            return Ok(());
+        }
+        // rfm69's send waits for PacketSent and drops back to standby, so the
+        // settings are only on for as long as the packet takes. they come off
+        // again even if the send failed, since receiving with them on can
+        // damage the receiver.
+        self.high_power_settings(true)?;
+        let sent = self.rfm.send(buf).map_err(Rfm69Error::from);
+        self.high_power_settings(false)?;
+        sent?;
+        Ok(())
+    }
+
+    fn recv(&mut self, buf: &mut [u8], wait: Duration) -> Result<usize> {
+        self.rfm.mode(Mode::Receiver).map_err(Rfm69Error::from)?;
+        // DIO0 stays high until the FIFO is read, so a packet that came in
+        // before we started waiting (and so whose rising edge we missed)
+        // still shows up here
+        if !self.dio0.is_high() {
+            let edge = self.dio0.poll_interrupt(true, Some(wait))
+                .chain_err(|| ErrorKind::RadioError("waiting for DIO0".into()))?;
+            if edge.is_none() && !self.dio0.is_high() {
+                return Ok(0);
+            }
+        }
+        self.read_packet(buf)
+    }
+
+    fn rssi(&self) -> f32 {
+        self.rfm.rssi()
+    }
+
+    fn channel_rssi(&mut self) -> Result<f32> {
+        self.rfm.mode(Mode::Receiver).map_err(Rfm69Error::from)?;
+        // RssiStart kicks off a measurement and RssiDone says when it's in;
+        // it only takes a couple of bit times
+        self.rfm.write(Registers::RssiConfig, RSSI_START).map_err(Rfm69Error::from)?;
+        let started = Instant::now();
+        while self.rfm.read(Registers::RssiConfig).map_err(Rfm69Error::from)? & RSSI_DONE == 0 {
+            if started.elapsed() > RSSI_TIMEOUT {
+                return Err(ErrorKind::RadioError("RSSI measurement never finished".into()).into());
+            }
+        }
+        Ok(rssi_dbm(self.rfm.read(Registers::RssiValue).map_err(Rfm69Error::from)?))
+    }
+}
+
+// the value of each of DUMPED_REGISTERS. read_all_regs gets the ones from
+// 0x01 to 0x4f in one go; the test registers past those are read one by one.
+pub fn read_registers(rfm: &mut Rfm69<OutputPin, Spi, linux_embedded_hal::Delay>) -> Result<Vec<(Registers, u8)>> {
+    let values = rfm.read_all_regs().map_err(Rfm69Error::from)?;
+    DUMPED_REGISTERS.iter().map(|register| {
+        let value = match usize::from(register.addr()).checked_sub(1).and_then(|index| values.get(index)) {
+            Some(value) => *value,
+            None => rfm.read(*register).map_err(Rfm69Error::from)?
+        };
+        Ok((*register, value))
+    }).collect()
+}
+
+// one line per register: address, name, and the value in hex and binary
+pub fn register_table(registers: &[(Registers, u8)]) -> Vec<String> {
+    registers.iter().map(|(register, value)| {
+        format!("0x{:02x}  {:<16} 0x{:02x}  {:08b}", register.addr(), format!("{:?}", register), value, value)
+    }).collect()
+}
+
+// RegRssiValue holds the RSSI as -2 times the dBm, so 0 to -127.5 dBm in half
+// dB steps. rfm69's rssi() does this same conversion.
+pub fn rssi_dbm(raw: u8) -> f32 {
+    -f32::from(raw) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rssi_register_is_minus_half_dbm() {
+        assert_eq!(rssi_dbm(0), 0.0);
+        assert_eq!(rssi_dbm(145), -72.5);
+        assert_eq!(rssi_dbm(255), -127.5);
+    }
+
+    #[test]
+    fn registers_are_labeled() {
+        assert_eq!(register_table(&[(Registers::OpMode, 0x04), (Registers::TestPa1, 0x55)]),
+                   vec!["0x01  OpMode           0x04  00000100", "0x5a  TestPa1          0x55  01010101"]);
+        // in address order, with no repeats
+        assert!(DUMPED_REGISTERS.windows(2).all(|pair| pair[0].addr() < pair[1].addr()));
+    }
+}
@@ -0,0 +1,81 @@
+// for when nothing is being heard and the rover might not be where it's
+// supposed to be, on the band: a crystal that's drifted, or the other bonnet
+// variant. listen on each frequency in a list in turn, round and round, until
+// a good packet turns up on one of them, and stay there.
+
+use crate::errors::*;
+use crate::link_stats::LinkStats;
+use crate::messages::*;
+use crate::radio::{ RadioConfig, RadioLink };
+use log::{ debug, info };
+use serde::Deserialize;
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanConfig {
+    pub frequencies_mhz: Vec<f64>,  // in the order to try them
+    pub dwell_ms: u64,              // how long to listen on each; longer than the rover's transmit interval
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            frequencies_mhz: vec![915.0, 868.0],
+            dwell_ms: 5000
+        }
+    }
+}
+
+// tune sets the radio to a frequency in Hz. returns the frequency the rover
+// was heard on, or None if running went false first. a RadioError stops the
+// scan; anything else (a bad packet, say) just means keep going.
+pub fn scan_for_rover<R, T>(radio: &mut R, config: &RadioConfig, scan: &ScanConfig, stats: &mut LinkStats,
+                            running: &AtomicBool, mut tune: T) -> Result<Option<f64>>
+        where R: RadioLink, T: FnMut(&mut R, f64) -> Result<()> {
+    if scan.frequencies_mhz.is_empty() {
+        return Err("no frequencies to scan".into());
+    }
+    while running.load(Ordering::SeqCst) {
+        for mhz in &scan.frequencies_mhz {
+            let frequency_hz = mhz * 1e6;
+            tune(radio, frequency_hz)?;
+            info!("Scanning {} MHz", mhz);
+            match RoverMessage::receive_any(radio, config, stats, scan.dwell_ms, false) {
+                Ok((message, _)) => {
+                    info!("Heard the rover on {} MHz: {}", mhz, message);
+                    return Ok(Some(frequency_hz));
+                },
+                Err(e @ Error(ErrorKind::RadioError(_), _)) => return Err(e),
+                Err(e) => debug!("Nothing on {} MHz: {}", mhz, e)
+            }
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::tests::MockRadio;
+
+    #[test]
+    fn scan_stops_where_the_rover_is() {
+        let scan = ScanConfig { frequencies_mhz: vec![915.0, 868.0], dwell_ms: 10 };
+        let mut tuned = Vec::new();
+        let heard = scan_for_rover(&mut MockRadio::default(), &RadioConfig::default(), &scan, &mut LinkStats::new(),
+                                   &AtomicBool::new(true), |radio: &mut MockRadio, frequency_hz| {
+            tuned.push(frequency_hz);
+            // the rover is on 868, and only heard on the second time round
+            if frequency_hz == 868e6 && tuned.len() > 2 {
+                radio.queue_sequence(&RoverMessage::Pong { timestamp: Default::default() }, 0);
+            }
+            Ok(())
+        }).unwrap();
+        assert_eq!(heard, Some(868e6));
+        assert_eq!(tuned, vec![915e6, 868e6, 915e6, 868e6]);
+    }
+}
@@ -0,0 +1,219 @@
+// an RFM69 on the far end of a serial port, for running the station on a
+// machine without the bonnet: an Arduino (or similar) with the radio wired to
+// it, set up with the same frequency, bit rate and key as the rover, passing
+// packets back and forth. every frame on the wire, either way, is
+//
+//   0x7e, kind, n, n bytes of payload, checksum
+//
+// where the checksum is the XOR of kind, n and the payload. the kinds are
+//   'T'  station to gateway: transmit the payload, a packet starting with its length byte
+//   'R'  gateway to station: a packet was received; the payload is the raw
+//        RSSI register value followed by the packet
+//   'S'  station to gateway, no payload: measure the channel RSSI; the
+//        gateway answers with an 'S' frame holding the raw RSSI register value
+
+use crate::errors::*;
+use crate::radio::{ rssi_dbm, RadioLink };
+use log::debug;
+use serde::Deserialize;
+use serialport::SerialPort;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::{ self, Read, Write };
+use std::time::{ Duration, Instant };
+
+const FRAME_START: u8 = 0x7e;
+const TRANSMIT: u8 = b'T';
+const RECEIVED: u8 = b'R';
+const CHANNEL_RSSI: u8 = b'S';
+const MAX_JUNK: usize = 512;  // bytes to skip looking for a frame start before deciding the line is garbage
+const CHANNEL_RSSI_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SerialConfig {
+    pub port: String,
+    pub baud: u32,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            port: String::from("/dev/ttyUSB0"),
+            baud: 115200
+        }
+    }
+}
+
+pub struct SerialRadio {
+    config: SerialConfig,
+    port: Box<dyn SerialPort>,
+    rssi: f32,
+    received: VecDeque<(Vec<u8>, f32)>,  // packets that turned up while waiting for something else
+}
+
+impl SerialRadio {
+    pub fn open(config: &SerialConfig) -> Result<SerialRadio> {
+        Ok(SerialRadio { config: config.clone(),
+                         port: open_port(config)?,
+                         rssi: 0.0,
+                         received: VecDeque::new() })
+    }
+
+    // close and reopen the port, the nearest thing to resetting the radio
+    pub fn reopen(&mut self) -> Result<()> {
+        self.port = open_port(&self.config)?;
+        self.received.clear();
+        Ok(())
+    }
+
+    // the next frame, if one starts within wait
+    fn read_frame(&mut self, wait: Duration) -> Result<Option<(u8, Vec<u8>)>> {
+        self.port.set_timeout(wait).chain_err(|| ErrorKind::RadioError("setting serial timeout".into()))?;
+        read_frame(&mut self.port)
+    }
+}
+
+impl RadioLink for SerialRadio {
+    fn send(&mut self, buf: &[u8]) -> Result<()> {
+        self.port.write_all(&frame(TRANSMIT, buf)?)
+            .and_then(|_| self.port.flush())
+            .chain_err(|| ErrorKind::RadioError("writing to serial gateway".into()))
+    }
+
+    fn recv(&mut self, buf: &mut [u8], wait: Duration) -> Result<usize> {
+        let (packet, rssi) = match self.received.pop_front() {
+            Some(packet) => packet,
+            None => match self.read_frame(wait)? {
+                Some((RECEIVED, payload)) => received_packet(&payload)?,
+                Some((kind, _)) => {
+                    debug!("Ignoring unexpected '{}' frame from serial gateway", char::from(kind));
+                    return Ok(0);
+                },
+                None => return Ok(0)
+            }
+        };
+        if packet.len() > buf.len() {
+            return Err(ErrorKind::RadioError(format!("{} byte packet from serial gateway won't fit in {} bytes",
+                                                     packet.len(), buf.len())).into());
+        }
+        buf[..packet.len()].copy_from_slice(&packet);
+        self.rssi = rssi;
+        Ok(packet.len())
+    }
+
+    fn rssi(&self) -> f32 {
+        self.rssi
+    }
+
+    fn channel_rssi(&mut self) -> Result<f32> {
+        self.port.write_all(&frame(CHANNEL_RSSI, &[])?)
+            .and_then(|_| self.port.flush())
+            .chain_err(|| ErrorKind::RadioError("writing to serial gateway".into()))?;
+        let started = Instant::now();
+        while let Some(wait) = CHANNEL_RSSI_TIMEOUT.checked_sub(started.elapsed()) {
+            match self.read_frame(wait)? {
+                Some((CHANNEL_RSSI, payload)) if payload.len() == 1 => return Ok(rssi_dbm(payload[0])),
+                // a packet can arrive before the answer; keep it for recv
+                Some((RECEIVED, payload)) => self.received.push_back(received_packet(&payload)?),
+                Some((kind, _)) => debug!("Ignoring unexpected '{}' frame from serial gateway", char::from(kind)),
+                None => break
+            }
+        }
+        Err(ErrorKind::RadioError("serial gateway didn't report the channel RSSI".into()).into())
+    }
+}
+
+fn open_port(config: &SerialConfig) -> Result<Box<dyn SerialPort>> {
+    serialport::new(&config.port, config.baud).open()
+        .chain_err(|| ErrorKind::RadioError(format!("opening serial port {}", config.port)))
+}
+
+fn frame(kind: u8, payload: &[u8]) -> Result<Vec<u8>> {
+    let length = u8::try_from(payload.len())
+        .map_err(|_| ErrorKind::RadioError(format!("{} bytes is too long for one serial frame", payload.len())))?;
+    let mut frame = vec![FRAME_START, kind, length];
+    frame.extend_from_slice(payload);
+    frame.push(checksum(kind, payload));
+    Ok(frame)
+}
+
+fn checksum(kind: u8, payload: &[u8]) -> u8 {
+    // the length is payload.len(), which frame has already checked fits in a u8
+    payload.iter().fold(kind ^ payload.len() as u8, |sum, byte| sum ^ byte)
+}
+
+fn received_packet(payload: &[u8]) -> Result<(Vec<u8>, f32)> {
+    match payload.split_first() {
+        Some((rssi, packet)) if !packet.is_empty() => Ok((packet.to_vec(), rssi_dbm(*rssi))),
+        _ => Err(ErrorKind::RadioError("empty packet from serial gateway".into()).into())
+    }
+}
+
+// skip anything before the frame start, then read the rest of the frame. a
+// timeout (or end of input) before the start means there was nothing to read;
+// one partway through, or a bad checksum, is a framing error.
+fn read_frame<P: Read + ?Sized>(port: &mut P) -> Result<Option<(u8, Vec<u8>)>> {
+    let mut byte = [0];
+    let mut skipped = 0;
+    loop {
+        match port.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) if byte[0] == FRAME_START => break,
+            Ok(_) => skipped += 1,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(e).chain_err(|| ErrorKind::RadioError("reading from serial gateway".into()))
+        }
+        if skipped > MAX_JUNK {
+            return Err(ErrorKind::RadioError(format!("no frame start from serial gateway in {} bytes", skipped)).into());
+        }
+    }
+    if skipped > 0 {
+        debug!("Skipped {} bytes from serial gateway before a frame start", skipped);
+    }
+    let mut header = [0; 2];
+    read_rest(port, &mut header)?;
+    let (kind, length) = (header[0], header[1]);
+    let mut payload = vec![0; length as usize + 1];
+    read_rest(port, &mut payload)?;
+    let sum = payload.pop().unwrap_or_default();
+    if sum != checksum(kind, &payload) {
+        return Err(ErrorKind::RadioError(format!("bad checksum on '{}' frame from serial gateway", char::from(kind))).into());
+    }
+    Ok(Some((kind, payload)))
+}
+
+fn read_rest<P: Read + ?Sized>(port: &mut P, buf: &mut [u8]) -> Result<()> {
+    port.read_exact(buf).chain_err(|| ErrorKind::RadioError("serial gateway frame cut short".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn frames_round_trip() {
+        let mut line = vec![0x00, 0x55];  // noise before the frame
+        line.extend(frame(RECEIVED, &[145, 3, 1, 2, 3]).unwrap());
+        let mut port = Cursor::new(line);
+        let (kind, payload) = read_frame(&mut port).unwrap().unwrap();
+        assert_eq!(kind, RECEIVED);
+        assert_eq!(received_packet(&payload).unwrap(), (vec![3, 1, 2, 3], -72.5));
+        // nothing more to read
+        assert_eq!(read_frame(&mut port).unwrap(), None);
+    }
+
+    #[test]
+    fn framing_errors_are_radio_errors() {
+        let mut bad = frame(TRANSMIT, &[1, 2]).unwrap();
+        *bad.last_mut().unwrap() ^= 0xff;
+        let short = frame(TRANSMIT, &[1, 2]).unwrap()[..4].to_vec();
+        for line in [bad, short] {
+            match read_frame(&mut Cursor::new(line)) {
+                Err(Error(ErrorKind::RadioError(_), _)) => (),
+                other => panic!("expected a RadioError, got {:?}", other)
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+// a CSV of signal strength against the rover's position, one row per
+// telemetry packet, for range testing and antenna work: plot it and there's a
+// coverage map. both ends' view of the link goes in, the RSSI the station
+// measured and the one the rover reports, since they needn't agree.
+
+use crate::errors::*;
+use crate::messages::*;
+use std::fs::{ File, OpenOptions };
+use std::io::Write;
+use std::path::Path;
+
+const CSV_HEADER: &str = "timestamp,station_rssi,rover_rssi,lat,long,alt,sats";
+
+pub struct SignalLogger {
+    file: File,
+}
+
+impl SignalLogger {
+    // open (or create) the log at path, appending to whatever is already there.
+    // a brand new file gets a header row.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<SignalLogger> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)
+            .chain_err(|| format!("opening signal log {}", path.display()))?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", CSV_HEADER)?;
+            file.flush()?;
+        }
+        Ok(SignalLogger { file })
+    }
+
+    // write a row for a telemetry packet received at station_rssi dBm. only
+    // telemetry says where the rover is, so anything else is skipped.
+    pub fn log(&mut self, message: &RoverMessage, station_rssi: f32) -> Result<()> {
+        if let RoverMessage::TelemetryMessage { timestamp, location, signal_strength, .. } = message {
+            writeln!(self.file, "20{:02}-{:02}-{:02} {:02}:{:02}:{:02},{},{},{},{},{},{}",
+                     timestamp.year, timestamp.month, timestamp.day,
+                     timestamp.hour, timestamp.minute, timestamp.second,
+                     station_rssi, signal_strength,
+                     location.gps_lat, location.gps_long, location.gps_alt, location.gps_sats)?;
+            self.file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn one_row_per_telemetry_packet() {
+        let path = std::env::temp_dir().join(format!("ground_control_signal_{}.csv", std::process::id()));
+        let timestamp = RoverTimestamp { year: 21, month: 6, day: 14, hour: 12, minute: 3, second: 45 };
+        let telemetry = RoverMessage::TelemetryMessage {
+            timestamp: timestamp.clone(),
+            location: RoverLocData { gps_lat: 37.5, gps_long: -122.25, gps_alt: 30.5, gps_sats: 7, ..Default::default() },
+            signal_strength: -81,
+            free_memory: 1024,
+            battery_millivolts: 7400,
+            status: "OK".into()
+        };
+        let mut logger = SignalLogger::new(&path).unwrap();
+        logger.log(&telemetry, -72.5).unwrap();
+        logger.log(&RoverMessage::Pong { timestamp }, -70.0).unwrap();
+        let contents = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents.unwrap(), format!("{}\n2021-06-14 12:03:45,-72.5,-81,37.5,-122.25,30.5,7\n", CSV_HEADER));
+    }
+}
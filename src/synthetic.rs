@@ -0,0 +1,106 @@
+// made up telemetry, for trying out the log, MQTT, InfluxDB, HTTP and
+// WebSocket outputs and whatever's downstream of them without a rover. the
+// pretend rover drives in a straight line from a starting point at a steady
+// speed, its signal strength swings up and down, and its battery slowly runs
+// down, which is enough for a track on a map and some lines on a graph.
+
+use crate::dead_reckoning::destination;
+use crate::messages::*;
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+const SATS: u8 = 8;
+const FREE_MEMORY: u16 = 1024;
+const FULL_BATTERY_MILLIVOLTS: u16 = 8400;
+const EMPTY_BATTERY_MILLIVOLTS: u16 = 6400;
+const RSSI_MIDDLE_DBM: f64 = -75.0;
+const RSSI_SWING_DBM: f64 = 15.0;   // either side of the middle
+const RSSI_PERIOD_FRAMES: f64 = 60.0;
+const STATUS: &str = "SYNTHETIC";   // so it's never mistaken for the real thing
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyntheticConfig {
+    pub lat: f64,             // where the pretend rover starts, in decimal degrees
+    pub lon: f64,
+    pub alt_m: f32,
+    pub speed_mps: f32,
+    pub heading_deg: u16,
+    pub interval_ms: u64,     // between packets
+}
+
+impl Default for SyntheticConfig {
+    fn default() -> Self {
+        Self {
+            lat: 37.42,
+            lon: -122.08,
+            alt_m: 30.0,
+            speed_mps: 1.0,
+            heading_deg: 45,
+            interval_ms: 1000
+        }
+    }
+}
+
+pub struct TelemetryGenerator {
+    config: SyntheticConfig,
+    frames: u64,
+}
+
+impl TelemetryGenerator {
+    pub fn new(config: &SyntheticConfig) -> TelemetryGenerator {
+        TelemetryGenerator { config: config.clone(), frames: 0 }
+    }
+
+    // the next packet, stamped with the time now
+    pub fn next_telemetry(&mut self) -> RoverMessage {
+        let frames = self.frames as f64;
+        self.frames += 1;
+        let travelled = f64::from(self.config.speed_mps) * frames * self.config.interval_ms as f64 / 1000.0;
+        let (lat, lon) = destination(self.config.lat, self.config.lon, self.config.heading_deg.into(), travelled);
+        let rssi = RSSI_MIDDLE_DBM + RSSI_SWING_DBM * (frames * std::f64::consts::TAU / RSSI_PERIOD_FRAMES).sin();
+        let used = u16::try_from(self.frames).unwrap_or(u16::MAX);
+        RoverMessage::TelemetryMessage {
+            timestamp: RoverTimestamp::now(),
+            location: RoverLocData { gps_lat: lat as f32,
+                                     gps_long: lon as f32,
+                                     gps_alt: self.config.alt_m,
+                                     gps_speed: self.config.speed_mps,
+                                     gps_sats: SATS,
+                                     gps_hdg: self.config.heading_deg },
+            signal_strength: rssi.round() as i16,
+            free_memory: FREE_MEMORY,
+            battery_millivolts: FULL_BATTERY_MILLIVOLTS.saturating_sub(used).max(EMPTY_BATTERY_MILLIVOLTS),
+            status: STATUS.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_pretend_rover_keeps_moving() {
+        let config = SyntheticConfig { lat: 0.0, lon: 0.0, speed_mps: 10.0, heading_deg: 90, interval_ms: 2000, ..Default::default() };
+        let mut generator = TelemetryGenerator::new(&config);
+        let packets: Vec<RoverMessage> = (0..16).map(|_| generator.next_telemetry()).collect();
+        let (first, last) = match (&packets[0], &packets[15]) {
+            (RoverMessage::TelemetryMessage { location: first, .. }, RoverMessage::TelemetryMessage { location: last, .. }) => (first, last),
+            _ => panic!("expected telemetry")
+        };
+        assert_eq!((first.gps_lat, first.gps_long), (0.0, 0.0));
+        // 15 packets 2 s apart at 10 m/s, due east along the equator
+        let (distance, bearing) = last.distance_bearing_from(0.0, 0.0);
+        assert!((distance - 300.0).abs() < 0.5, "{}", distance);
+        assert!((bearing - 90.0).abs() < 0.01, "{}", bearing);
+        // a quarter of the way round the swing
+        match &packets[15] {
+            RoverMessage::TelemetryMessage { signal_strength, status, .. } => {
+                assert_eq!(*signal_strength, -60);
+                assert_eq!(status, STATUS);
+            },
+            _ => unreachable!()
+        }
+    }
+}
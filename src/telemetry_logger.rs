@@ -0,0 +1,86 @@
+// appends received telemetry to a CSV file for post-mission analysis
+
+use crate::errors::*;
+use crate::messages::*;
+use std::fs::{ File, OpenOptions };
+use std::io::Write;
+use std::path::Path;
+
+const CSV_HEADER: &str = "timestamp,lat,long,alt,speed,sats,heading,signal_strength,free_memory,battery_millivolts,status";
+
+pub struct TelemetryLogger {
+    file: File,
+}
+
+impl TelemetryLogger {
+    // open (or create) the log at path, appending to whatever is already there.
+    // a brand new file gets a header row.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<TelemetryLogger> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)
+            .chain_err(|| format!("opening telemetry log {}", path.display()))?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", CSV_HEADER)?;
+            file.flush()?;
+        }
+        Ok(TelemetryLogger { file })
+    }
+
+    // write one row for a TelemetryMessage. flushed immediately so a crash
+    // doesn't lose anything.
+    pub fn log(&mut self, telemetry: &RoverMessage) -> Result<()> {
+        match telemetry {
+            RoverMessage::TelemetryMessage { timestamp, location, signal_strength, free_memory, battery_millivolts, status } => {
+                writeln!(self.file, "20{:02}-{:02}-{:02} {:02}:{:02}:{:02},{},{},{},{},{},{},{},{},{},{}",
+                         timestamp.year, timestamp.month, timestamp.day,
+                         timestamp.hour, timestamp.minute, timestamp.second,
+                         location.gps_lat, location.gps_long, location.gps_alt, location.gps_speed,
+                         location.gps_sats, location.gps_hdg,
+                         signal_strength, free_memory, battery_millivolts,
+                         TelemetryLogger::quote(status))?;
+                self.file.flush()?;
+                Ok(())
+            },
+            _ => Err(format!("Cannot log {:?} as telemetry", telemetry).into())
+        }
+    }
+
+    // push anything still buffered out to disk
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.flush()?;
+        Ok(())
+    }
+
+    // status is free text from the rover, so it may contain commas or quotes
+    fn quote(s: &str) -> String {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn header_once_then_a_row_per_packet() {
+        let path = std::env::temp_dir().join(format!("ground_control_telemetry_{}.csv", std::process::id()));
+        let telemetry = RoverMessage::TelemetryMessage {
+            timestamp: RoverTimestamp { year: 21, month: 6, day: 14, hour: 12, minute: 3, second: 45 },
+            location: RoverLocData { gps_lat: 37.5, gps_long: -122.25, gps_alt: 30.5, gps_speed: 1.5, gps_sats: 7, gps_hdg: 90 },
+            signal_strength: -81,
+            free_memory: 1024,
+            battery_millivolts: 7400,
+            status: "STUCK, \"help\"".into()
+        };
+        let row = "2021-06-14 12:03:45,37.5,-122.25,30.5,1.5,7,90,-81,1024,7400,\"STUCK, \"\"help\"\"\"";
+        TelemetryLogger::new(&path).unwrap().log(&telemetry).unwrap();
+        // opening it again appends without another header
+        let mut logger = TelemetryLogger::new(&path).unwrap();
+        logger.log(&telemetry).unwrap();
+        assert!(logger.log(&RoverMessage::Pong { timestamp: RoverTimestamp::now() }).is_err());
+        let contents = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents.unwrap(), format!("{}\n{}\n{}\n", CSV_HEADER, row, row));
+    }
+}
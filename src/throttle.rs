@@ -0,0 +1,49 @@
+// holds back whatever writes or forwards telemetry when the rover is sending
+// faster than anyone needs it kept: at most one record per interval gets
+// through, the rest are dropped. without an interval everything gets through.
+
+use std::time::{ Duration, Instant };
+
+pub struct Throttle {
+    interval: Option<Duration>,
+    last: Option<Instant>,  // when the last record was let through
+}
+
+impl Throttle {
+    pub fn new(interval: Option<Duration>) -> Throttle {
+        Throttle { interval, last: None }
+    }
+
+    // whether a record arriving now should go through; if so, it starts the
+    // next interval
+    pub fn ready(&mut self) -> bool {
+        self.ready_at(Instant::now())
+    }
+
+    fn ready_at(&mut self, now: Instant) -> bool {
+        match (self.interval, self.last) {
+            (Some(interval), Some(last)) if now.duration_since(last) < interval => false,
+            _ => {
+                self.last = Some(now);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_record_per_interval() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut throttle = Throttle::new(Some(Duration::from_millis(1000)));
+        let passed: Vec<u64> = [0, 400, 999, 1000, 1500, 2100].iter().cloned()
+            .filter(|ms| throttle.ready_at(at(*ms))).collect();
+        assert_eq!(passed, vec![0, 1000, 2100]);
+        let mut off = Throttle::new(None);
+        assert!(off.ready_at(at(0)) && off.ready_at(at(0)));
+    }
+}
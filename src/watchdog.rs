@@ -0,0 +1,122 @@
+// a station whose receive loop has hung (an SPI transfer that takes far too
+// long, say, or a recv whose timeout misbehaves) looks alive but hears
+// nothing. so the loop beats a heartbeat each time round, and a watchdog
+// thread checks on it: if it goes quiet for longer than the threshold, the
+// watchdog says so loudly and trips, and the loop resets the radio as soon as
+// it gets going again. the watchdog can't reset the radio itself, since
+// whatever is stuck is holding on to it. the heartbeat is paused during
+// command sessions, since the operator can take as long as they like at the
+// prompt.
+
+use log::error;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+const CHECKS_PER_THRESHOLD: u32 = 4;
+
+// without a threshold there's no thread, and it never trips
+pub struct Watchdog {
+    heartbeat: Option<Arc<Heartbeat>>,
+}
+
+impl Watchdog {
+    // start watching in a background thread
+    pub fn start(threshold: Option<Duration>) -> Watchdog {
+        let heartbeat = threshold.map(|threshold| {
+            let heartbeat = Arc::new(Heartbeat::new(threshold));
+            let watched = heartbeat.clone();
+            thread::spawn(move || loop {
+                thread::sleep(threshold / CHECKS_PER_THRESHOLD);
+                watched.watch(Instant::now());
+            });
+            heartbeat
+        });
+        Watchdog { heartbeat }
+    }
+
+    // the loop's come round again; this ends a pause too
+    pub fn beat(&self) {
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.beat();
+        }
+    }
+
+    // stop watching until the next beat
+    pub fn pause(&self) {
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.paused.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // whether the loop has hung since this was last asked, meaning the radio
+    // wants a reset
+    pub fn tripped(&self) -> bool {
+        match &self.heartbeat {
+            Some(heartbeat) => heartbeat.tripped.swap(false, Ordering::SeqCst),
+            None => false
+        }
+    }
+}
+
+// the last beat is kept as millis since started, so both threads can get at
+// it without a lock
+struct Heartbeat {
+    started: Instant,
+    last_beat_ms: AtomicU64,
+    threshold: Duration,
+    paused: AtomicBool,
+    tripped: AtomicBool,
+}
+
+impl Heartbeat {
+    fn new(threshold: Duration) -> Heartbeat {
+        Heartbeat { started: Instant::now(), last_beat_ms: AtomicU64::new(0), threshold,
+                    paused: AtomicBool::new(false), tripped: AtomicBool::new(false) }
+    }
+
+    fn beat(&self) {
+        self.last_beat_ms.store(self.started.elapsed().as_millis() as u64, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    // trip if it's been too long since the last beat, saying so the first
+    // time only
+    fn watch(&self, now: Instant) {
+        let silence = now.duration_since(self.started)
+            .saturating_sub(Duration::from_millis(self.last_beat_ms.load(Ordering::SeqCst)));
+        if silence > self.threshold && !self.paused.load(Ordering::SeqCst) && !self.tripped.swap(true, Ordering::SeqCst) {
+            error!("RECEIVE LOOP HUNG: it hasn't come round for {} s; the radio will be reset when it does",
+                   silence.as_secs());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trips_once_the_heartbeat_goes_quiet() {
+        let watchdog = Watchdog { heartbeat: Some(Arc::new(Heartbeat::new(Duration::from_secs(30)))) };
+        let heartbeat = watchdog.heartbeat.as_ref().unwrap();
+        let started = heartbeat.started;
+        heartbeat.watch(started + Duration::from_secs(29));
+        assert!(!watchdog.tripped());
+        heartbeat.watch(started + Duration::from_secs(31));
+        assert!(watchdog.tripped());
+        // asking resets it
+        assert!(!watchdog.tripped());
+        // a command session can take as long as it likes
+        watchdog.pause();
+        heartbeat.watch(started + Duration::from_secs(300));
+        assert!(!watchdog.tripped());
+        watchdog.beat();
+        assert!(!heartbeat.paused.load(Ordering::SeqCst));
+        heartbeat.watch(Instant::now());
+        assert!(!watchdog.tripped());
+        // and without a threshold there's nothing to trip
+        assert!(!Watchdog::start(None).tripped());
+    }
+}
@@ -0,0 +1,164 @@
+// pushes each telemetry packet as JSON to browsers connected over a
+// WebSocket, so a web dashboard can update as packets arrive instead of
+// polling the HTTP server. the server runs on a single threaded tokio runtime
+// on a thread of its own, and the receive loop hands it telemetry through a
+// broadcast channel, which never blocks: a client that falls too far behind
+// just misses packets. the async machinery is only built with
+// `--features websocket`, so a station that doesn't want it doesn't pay for it.
+
+use crate::errors::*;
+use crate::messages::*;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    pub port: u16,
+    pub max_clients: usize,  // anyone past this many is turned away
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            port: 8081,
+            max_clients: 8
+        }
+    }
+}
+
+#[cfg(feature = "websocket")]
+pub use server::{ start, TelemetryFeed };
+
+#[cfg(feature = "websocket")]
+mod server {
+    use super::*;
+    use futures_util::{ SinkExt, StreamExt };
+    use log::{ debug, info, warn };
+    use std::sync::Arc;
+    use std::sync::atomic::{ AtomicUsize, Ordering };
+    use std::thread;
+    use tokio::net::{ TcpListener, TcpStream };
+    use tokio::sync::broadcast::{ self, error::RecvError };
+    use tokio_tungstenite::tungstenite::{ self, Message };
+
+    const QUEUE_SIZE: usize = 16;  // packets waiting to go out to each client; older ones are dropped once full
+
+    // the receive loop's end of the channel
+    pub struct TelemetryFeed {
+        sender: broadcast::Sender<String>,
+    }
+
+    impl TelemetryFeed {
+        pub fn publish(&self, telemetry: &RoverMessage) -> Result<()> {
+            // sending only fails when nobody's connected, which is fine
+            let _ = self.sender.send(serde_json::to_string(telemetry)?);
+            Ok(())
+        }
+    }
+
+    // start listening on the configured port in a background thread
+    pub fn start(config: &WebSocketConfig) -> Result<TelemetryFeed> {
+        listen(config).map(|(feed, _)| feed)
+    }
+
+    // start, and say which port it ended up on (whatever was free, for port 0)
+    fn listen(config: &WebSocketConfig) -> Result<(TelemetryFeed, u16)> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build()
+            .chain_err(|| "starting WebSocket runtime")?;
+        let listener = runtime.block_on(TcpListener::bind(("0.0.0.0", config.port)))
+            .chain_err(|| format!("starting WebSocket server on port {}", config.port))?;
+        let port = listener.local_addr().chain_err(|| "finding the WebSocket server's port")?.port();
+        let (sender, _) = broadcast::channel(QUEUE_SIZE);
+        let clients = sender.clone();
+        let max_clients = config.max_clients;
+        thread::spawn(move || runtime.block_on(serve(listener, clients, max_clients)));
+        Ok((TelemetryFeed { sender }, port))
+    }
+
+    async fn serve(listener: TcpListener, sender: broadcast::Sender<String>, max_clients: usize) {
+        let connected = Arc::new(AtomicUsize::new(0));
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("Error accepting WebSocket client: {}", e);
+                    continue;
+                }
+            };
+            // dropping the stream hangs up on it
+            if connected.load(Ordering::SeqCst) >= max_clients {
+                warn!("Turning away WebSocket client {}: {} already connected", peer, max_clients);
+                continue;
+            }
+            connected.fetch_add(1, Ordering::SeqCst);
+            info!("WebSocket client {} connected", peer);
+            let telemetry = sender.subscribe();
+            let connected = connected.clone();
+            tokio::spawn(async move {
+                match client(stream, telemetry).await {
+                    Ok(()) => info!("WebSocket client {} disconnected", peer),
+                    Err(e) => info!("WebSocket client {} dropped: {}", peer, e)
+                }
+                connected.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    // forward telemetry to one client until it goes away
+    async fn client(stream: TcpStream, mut telemetry: broadcast::Receiver<String>) -> std::result::Result<(), tungstenite::Error> {
+        let (mut outgoing, mut incoming) = tokio_tungstenite::accept_async(stream).await?.split();
+        loop {
+            tokio::select! {
+                json = telemetry.recv() => match json {
+                    Ok(json) => outgoing.send(Message::Text(json)).await?,
+                    Err(RecvError::Lagged(missed)) => debug!("WebSocket client missed {} packets", missed),
+                    Err(RecvError::Closed) => return Ok(())
+                },
+                // clients have nothing to say, but reading is how a hang up
+                // gets noticed (and pings answered)
+                message = incoming.next() => match message {
+                    None | Some(Ok(Message::Close(_))) => return Ok(()),
+                    Some(Err(e)) => return Err(e),
+                    Some(Ok(_)) => ()
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::net::TcpStream;
+
+        #[test]
+        fn clients_get_telemetry_up_to_the_limit() {
+            let (feed, port) = listen(&WebSocketConfig { port: 0, max_clients: 1 }).unwrap();
+            let url = format!("ws://localhost:{}/", port);
+            let (mut browser, _) = tungstenite::client(&url, TcpStream::connect(("localhost", port)).unwrap()).unwrap();
+            let turned_away = tungstenite::client(&url, TcpStream::connect(("localhost", port)).unwrap());
+            assert!(turned_away.is_err());
+            // the client isn't subscribed until its handshake is done
+            thread::sleep(std::time::Duration::from_millis(100));
+            feed.publish(&RoverMessage::Pong { timestamp: RoverTimestamp { year: 21, month: 6, day: 14, hour: 12, minute: 3, second: 45 } }).unwrap();
+            let json = browser.read().unwrap().into_text().unwrap();
+            assert!(json.contains("\"minute\":3"), "{}", json);
+        }
+    }
+}
+
+// without the feature there's nothing to start, so a config that asks for
+// the server is an error rather than silently ignored
+#[cfg(not(feature = "websocket"))]
+pub struct TelemetryFeed;
+
+#[cfg(not(feature = "websocket"))]
+impl TelemetryFeed {
+    pub fn publish(&self, _telemetry: &RoverMessage) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "websocket"))]
+pub fn start(config: &WebSocketConfig) -> Result<TelemetryFeed> {
+    Err(format!("a [websocket] server on port {} needs a build with --features websocket", config.port).into())
+}